@@ -0,0 +1,150 @@
+use crate::usage::{BucketKind, UsageBar, UsageColor, UsageState};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Two `resets_at` values within this many seconds are treated as the same window.
+const SAME_WINDOW_TOLERANCE_SECONDS: i64 = 60;
+
+/// Only red states notify and can be acknowledged.
+pub fn is_alert(color: UsageColor) -> bool {
+    matches!(color, UsageColor::Red | UsageColor::RedBlink)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mark {
+    pub color: UsageColor,
+    pub resets_at: String,
+}
+
+/// Per-bucket record of the color seen in a given window.
+/// Used both for user acknowledgments and for not repeating notifications.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BucketMarks(HashMap<BucketKind, Mark>);
+
+impl BucketMarks {
+    pub fn mark(&mut self, bar: &UsageBar) {
+        self.0.insert(
+            bar.kind,
+            Mark {
+                color: bar.color,
+                resets_at: bar.resets_at.clone(),
+            },
+        );
+    }
+
+    pub fn forget(&mut self, kind: BucketKind) {
+        self.0.remove(&kind);
+    }
+
+    /// The mark still applies: same color, same window.
+    pub fn holds(&self, bar: &UsageBar) -> bool {
+        self.0.get(&bar.kind).is_some_and(|mark| {
+            mark.color == bar.color && same_window(&mark.resets_at, &bar.resets_at)
+        })
+    }
+
+    /// The bar is worse than what was marked, or the window has reset since.
+    pub fn is_escalation(&self, bar: &UsageBar) -> bool {
+        match self.0.get(&bar.kind) {
+            Some(mark) => {
+                bar.color.severity() > mark.color.severity()
+                    || !same_window(&mark.resets_at, &bar.resets_at)
+            }
+            None => true,
+        }
+    }
+
+    /// Drop marks that no longer hold for buckets present in `state`.
+    /// Returns whether anything was removed.
+    pub fn prune(&mut self, state: &UsageState) -> bool {
+        let before = self.0.len();
+        for bar in state.bars() {
+            if !self.holds(bar) {
+                self.0.remove(&bar.kind);
+            }
+        }
+        self.0.len() != before
+    }
+
+    /// Copy acknowledgment status onto the bars of `state`.
+    pub fn apply(&self, state: &mut UsageState) {
+        for bar in state.bars_mut() {
+            bar.acknowledged = is_alert(bar.color) && self.holds(bar);
+        }
+    }
+}
+
+pub(crate) fn same_window(a: &str, b: &str) -> bool {
+    match (a.parse::<DateTime<Utc>>(), b.parse::<DateTime<Utc>>()) {
+        (Ok(a), Ok(b)) => (a - b).num_seconds().abs() <= SAME_WINDOW_TOLERANCE_SECONDS,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, RESETS_AT};
+
+    fn bar(kind: BucketKind, color: UsageColor, resets_at: &str) -> UsageBar {
+        UsageBar {
+            resets_at: resets_at.to_string(),
+            ..test_support::bar(kind, color)
+        }
+    }
+
+    #[test]
+    fn acknowledgment_holds_within_same_color_and_window() {
+        let mut acks = BucketMarks::default();
+        acks.mark(&bar(BucketKind::Weekly, UsageColor::Red, RESETS_AT));
+
+        assert!(acks.holds(&bar(BucketKind::Weekly, UsageColor::Red, "2026-01-16T09:00:20Z")));
+        assert!(!acks.holds(&bar(BucketKind::Session, UsageColor::Red, RESETS_AT)));
+    }
+
+    #[test]
+    fn acknowledgment_ends_on_escalation_or_reset() {
+        let mut acks = BucketMarks::default();
+        acks.mark(&bar(BucketKind::Weekly, UsageColor::Red, RESETS_AT));
+
+        assert!(!acks.holds(&bar(BucketKind::Weekly, UsageColor::RedBlink, RESETS_AT)));
+        assert!(!acks.holds(&bar(BucketKind::Weekly, UsageColor::Red, "2026-01-23T09:00:00Z")));
+    }
+
+    #[test]
+    fn prune_keeps_marks_for_missing_buckets() {
+        let mut acks = BucketMarks::default();
+        acks.mark(&bar(BucketKind::Weekly, UsageColor::Red, RESETS_AT));
+        acks.mark(&bar(BucketKind::Session, UsageColor::Red, RESETS_AT));
+
+        let state = UsageState {
+            session: Some(bar(BucketKind::Session, UsageColor::Green, RESETS_AT)),
+            weekly: None,
+            last_updated: String::new(),
+            error: None,
+            extra: None,
+            plan: None,
+            other_buckets: Vec::new(),
+        };
+
+        assert!(acks.prune(&state));
+        assert!(acks.holds(&bar(BucketKind::Weekly, UsageColor::Red, RESETS_AT)));
+        assert!(!acks.holds(&bar(BucketKind::Session, UsageColor::Red, RESETS_AT)));
+    }
+
+    #[test]
+    fn escalation_only_when_worse_or_new_window() {
+        let mut notified = BucketMarks::default();
+        let red = bar(BucketKind::Weekly, UsageColor::Red, RESETS_AT);
+        assert!(notified.is_escalation(&red));
+
+        notified.mark(&bar(BucketKind::Weekly, UsageColor::RedBlink, RESETS_AT));
+        assert!(!notified.is_escalation(&red));
+        assert!(notified.is_escalation(&bar(
+            BucketKind::Weekly,
+            UsageColor::Red,
+            "2026-01-23T09:00:00Z"
+        )));
+    }
+}
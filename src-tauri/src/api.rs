@@ -1,12 +1,85 @@
+use crate::net::NetworkConfig;
 use crate::usage::ApiUsageResponse;
-use reqwest::header::{HeaderMap, HeaderValue, COOKIE, REFERER, USER_AGENT};
+use reqwest::cookie::{CookieStore, Jar};
+use reqwest::header::{HeaderMap, HeaderValue, REFERER};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-const BASE_URL: &str = "https://claude.ai";
+const BROWSER_USER_AGENT: &str =
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:147.0) Gecko/20100101 Firefox/147.0";
 
+/// Where usage is fetched from: claude.ai by default, or a mock or team-run caching relay.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiEndpoints {
+    pub base_url: String,
+    /// Usage endpoint under `base_url`; `{org_id}` is replaced with the organization ID.
+    pub usage_path: String,
+}
+
+impl Default for ApiEndpoints {
+    fn default() -> Self {
+        Self {
+            base_url: "https://claude.ai".to_string(),
+            usage_path: "/api/organizations/{org_id}/usage".to_string(),
+        }
+    }
+}
+
+impl ApiEndpoints {
+    pub fn validate(&self) -> Result<(), String> {
+        self.base()?;
+        if !self.usage_path.starts_with('/') || !self.usage_path.contains("{org_id}") {
+            return Err("Usage path must start with / and contain {org_id}".to_string());
+        }
+        Ok(())
+    }
+
+    /// The base URL with a trailing slash, so a relay's path prefix survives `join`.
+    fn base(&self) -> Result<Url, String> {
+        let mut url = Url::parse(self.base_url.trim())
+            .map_err(|e| format!("Invalid API base URL: {}", e))?;
+        match url.scheme() {
+            "https" => {}
+            // The session cookie must not cross the network in cleartext
+            "http" if is_loopback(&url) => {}
+            "http" => return Err("API base URL must use https:// unless it's on this machine".to_string()),
+            _ => return Err("API base URL must start with https://".to_string()),
+        }
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        Ok(url)
+    }
+}
+
+/// Whether `url` points at this machine, so plain http doesn't cross the network.
+pub(crate) fn is_loopback(url: &Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Long-lived claude.ai client. Share it as `Arc<ClaudeClient>`: the inner `reqwest::Client`
+/// keeps a connection pool (HTTP/2 where offered) and the cookie jar lives across polls.
 pub struct ClaudeClient {
     client: reqwest::Client,
-    session_key: String,
+    cookies: Arc<Jar>,
+    base_url: Url,
+    usage_path: String,
+    session_key: Mutex<String>,
     org_id: String,
+    /// Unrecognized fields and buckets already logged, so each is reported once.
+    reported_fields: Mutex<BTreeSet<String>>,
 }
 
 #[derive(Debug)]
@@ -16,43 +89,100 @@ pub struct ApiResult {
 }
 
 impl ClaudeClient {
-    pub fn new(session_key: &str, org_id: &str) -> Self {
-        let client = reqwest::Client::builder()
-            .redirect(reqwest::redirect::Policy::limited(3))
-            .build()
-            .expect("Failed to create HTTP client");
+    pub fn new(
+        session_key: &str,
+        org_id: &str,
+        endpoints: &ApiEndpoints,
+        network: &NetworkConfig,
+    ) -> Self {
+        Self::with_cookies(session_key, org_id, endpoints, network, Arc::new(Jar::default()))
+    }
 
-        Self {
+    /// Build a client that continues an existing cookie jar (e.g. after a settings change).
+    pub fn with_cookies(
+        session_key: &str,
+        org_id: &str,
+        endpoints: &ApiEndpoints,
+        network: &NetworkConfig,
+        cookies: Arc<Jar>,
+    ) -> Self {
+        let endpoints = endpoints.validate().map(|()| endpoints.clone()).unwrap_or_else(|e| {
+            eprintln!("[api] {}; using claude.ai", e);
+            ApiEndpoints::default()
+        });
+        let base_url = endpoints.base().expect("validated base URL");
+
+        let client = network.build_client(
+            reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::limited(3))
+                .cookie_provider(cookies.clone())
+                .pool_idle_timeout(Duration::from_secs(90))
+                .pool_max_idle_per_host(2)
+                .tcp_keepalive(Duration::from_secs(60)),
+            BROWSER_USER_AGENT,
+        );
+
+        let client = Self {
             client,
-            session_key: session_key.to_string(),
+            cookies,
+            base_url,
+            usage_path: endpoints.usage_path,
+            session_key: Mutex::new(String::new()),
             org_id: org_id.to_string(),
-        }
+            reported_fields: Mutex::default(),
+        };
+        client.update_session_key(session_key.to_string());
+        client
     }
 
-    pub fn session_key(&self) -> &str {
-        &self.session_key
+    pub fn session_key(&self) -> String {
+        self.session_key.lock().unwrap().clone()
     }
 
     pub fn org_id(&self) -> &str {
         &self.org_id
     }
 
-    pub fn update_session_key(&mut self, key: String) {
-        self.session_key = key;
+    pub fn update_session_key(&self, key: String) {
+        let cookie = format!("sessionKey={}; Path=/; HttpOnly", key);
+        self.cookies.add_cookie_str(&cookie, &self.base_url);
+        *self.session_key.lock().unwrap() = key;
+    }
+
+    pub fn cookies(&self) -> Arc<Jar> {
+        self.cookies.clone()
+    }
+
+    /// Cookies worth keeping across restarts, as a `Cookie` header value. The session key
+    /// is left out — it lives in the OS keychain.
+    pub fn export_cookies(&self) -> String {
+        let Some(header) = self.cookies.cookies(&self.base_url) else {
+            return String::new();
+        };
+        header
+            .to_str()
+            .unwrap_or_default()
+            .split("; ")
+            .filter(|pair| !pair.is_empty() && !pair.starts_with("sessionKey="))
+            .collect::<Vec<_>>()
+            .join("; ")
     }
 
+    /// Restore cookies saved by `export_cookies`.
+    pub fn import_cookies(&self, saved: &str) {
+        for pair in saved.split("; ") {
+            if !pair.is_empty() && !pair.starts_with("sessionKey=") {
+                self.cookies.add_cookie_str(pair, &self.base_url);
+            }
+        }
+    }
+
+    // No Cookie header here: sessionKey is in the jar, which would overwrite it anyway
     fn headers(&self) -> HeaderMap {
         let mut headers = HeaderMap::new();
-        headers.insert(
-            USER_AGENT,
-            HeaderValue::from_static(
-                "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:147.0) Gecko/20100101 Firefox/147.0",
-            ),
-        );
-        headers.insert(
-            REFERER,
-            HeaderValue::from_static("https://claude.ai/settings/usage"),
-        );
+        if let Ok(referer) = HeaderValue::from_str(&format!("{}settings/usage", self.base_url)) {
+            headers.insert(REFERER, referer);
+        }
         headers.insert(
             "anthropic-client-platform",
             HeaderValue::from_static("web_claude_ai"),
@@ -62,23 +192,19 @@ impl ClaudeClient {
             HeaderValue::from_static("application/json"),
         );
 
-        let cookie_value = format!("sessionKey={}", self.session_key);
-        if let Ok(val) = HeaderValue::from_str(&cookie_value) {
-            headers.insert(COOKIE, val);
-        }
-
         headers
     }
 
     pub async fn fetch_usage(&self) -> Result<ApiResult, String> {
-        let url = format!(
-            "{}/api/organizations/{}/usage",
-            BASE_URL, self.org_id
-        );
+        let path = self.usage_path.replace("{org_id}", &self.org_id);
+        let url = self
+            .base_url
+            .join(path.trim_start_matches('/'))
+            .map_err(|e| format!("Invalid usage URL: {}", e))?;
 
         let response = self
             .client
-            .get(&url)
+            .get(url)
             .headers(self.headers())
             .send()
             .await
@@ -114,6 +240,16 @@ impl ClaudeClient {
             .json()
             .await
             .map_err(|e| format!("Parse error: {}", e))?;
+        for (name, err) in &usage.bucket_errors {
+            eprintln!("[api] skipping usage bucket {}: {}", name, err);
+        }
+        let new_fields = usage.newly_seen_fields(&mut self.reported_fields.lock().unwrap());
+        for name in new_fields {
+            match usage.other.get(&name) {
+                Some(value) => eprintln!("[api] unrecognized usage field {}: {}", name, value),
+                None => eprintln!("[api] new usage bucket {}", name),
+            }
+        }
 
         Ok(ApiResult {
             usage,
@@ -121,3 +257,138 @@ impl ClaudeClient {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_claude::{self, MockClaude};
+    use wiremock::ResponseTemplate;
+
+    async fn fetch_error(response: ResponseTemplate) -> String {
+        let mock = MockClaude::start().await;
+        mock.respond("sk", response).await;
+        mock.client("sk").fetch_usage().await.unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn fetches_usage_with_session_cookie() {
+        let mock = MockClaude::start().await;
+        mock.respond("sk-live", mock_claude::usage(42.0, 17.0)).await;
+
+        let client = mock.client("sk-live");
+        let result = client.fetch_usage().await.unwrap();
+        assert_eq!(result.usage.buckets["five_hour"].utilization, 42.0);
+        assert_eq!(result.usage.buckets["seven_day"].utilization, 17.0);
+        assert!(result.refreshed_session_key.is_none());
+
+        // A second poll reuses the client and sends the same cookie
+        client.fetch_usage().await.unwrap();
+        assert_eq!(mock.cookies_sent().await, ["sessionKey=sk-live", "sessionKey=sk-live"]);
+    }
+
+    #[tokio::test]
+    async fn rotated_session_key_is_reported_and_used() {
+        let mock = MockClaude::start().await;
+        mock.respond("sk-old", mock_claude::rotating_usage(1.0, 1.0, "sk-new"))
+            .await;
+        mock.respond("sk-new", mock_claude::usage(2.0, 2.0)).await;
+
+        let client = mock.client("sk-old");
+        let first = client.fetch_usage().await.unwrap();
+        assert_eq!(first.refreshed_session_key.as_deref(), Some("sk-new"));
+
+        // The jar picked up the Set-Cookie before we got to update_session_key
+        let second = client.fetch_usage().await.unwrap();
+        assert_eq!(second.usage.buckets["five_hour"].utilization, 2.0);
+    }
+
+    #[tokio::test]
+    async fn maps_error_responses() {
+        let expired = "Session expired. Please update your session key.";
+        assert_eq!(fetch_error(ResponseTemplate::new(401)).await, expired);
+        assert_eq!(fetch_error(ResponseTemplate::new(403)).await, expired);
+        assert_eq!(
+            fetch_error(ResponseTemplate::new(429)).await,
+            "API error: HTTP 429 Too Many Requests"
+        );
+        assert_eq!(
+            fetch_error(ResponseTemplate::new(500)).await,
+            "API error: HTTP 500 Internal Server Error"
+        );
+
+        let malformed = ResponseTemplate::new(200).set_body_string("{\"five_hour\": ");
+        assert!(fetch_error(malformed).await.starts_with("Parse error:"));
+
+        // Unknown key: the mock's catch-all answers 401
+        let mock = MockClaude::start().await;
+        assert_eq!(mock.client("sk").fetch_usage().await.unwrap_err(), expired);
+    }
+
+    #[tokio::test]
+    async fn usage_path_template_resolves_under_relay_prefix() {
+        let mock = MockClaude::start().await;
+        mock.respond_at("/relay/v2/usage/org-test", "sk", mock_claude::usage(5.0, 6.0))
+            .await;
+
+        let endpoints = ApiEndpoints {
+            base_url: format!("{}/relay", mock.endpoints().base_url),
+            usage_path: "/v2/usage/{org_id}".to_string(),
+        };
+        assert!(endpoints.validate().is_ok());
+        let result = mock.client_with("sk", &endpoints).fetch_usage().await.unwrap();
+        assert_eq!(result.usage.buckets["five_hour"].utilization, 5.0);
+
+        let no_org = ApiEndpoints {
+            usage_path: "/usage".to_string(),
+            ..ApiEndpoints::default()
+        };
+        assert!(no_org.validate().is_err());
+        let bad_scheme = ApiEndpoints {
+            base_url: "ftp://relay.example".to_string(),
+            ..ApiEndpoints::default()
+        };
+        assert!(bad_scheme.validate().is_err());
+        let base = |url: &str| ApiEndpoints {
+            base_url: url.to_string(),
+            ..ApiEndpoints::default()
+        };
+        assert!(base("http://relay.example").validate().is_err());
+        assert!(base("http://localhost:8080/relay").validate().is_ok());
+        assert!(base("http://[::1]:8080").validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn slow_response_times_out() {
+        let slow = mock_claude::usage(1.0, 1.0).set_delay(std::time::Duration::from_secs(3));
+        assert!(fetch_error(slow).await.starts_with("Network error:"));
+    }
+
+    #[test]
+    fn exported_cookies_leave_out_the_session_key() {
+        let client = ClaudeClient::new(
+            "sk-old",
+            "org",
+            &ApiEndpoints::default(),
+            &NetworkConfig::default(),
+        );
+        client.cookies().add_cookie_str("cf_clearance=abc; Path=/", &client.base_url);
+        client.update_session_key("sk-new".to_string());
+
+        let header = client.cookies().cookies(&client.base_url).unwrap();
+        let header = header.to_str().unwrap();
+        assert!(header.contains("sessionKey=sk-new"));
+        assert!(!header.contains("sk-old"));
+
+        let saved = client.export_cookies();
+        assert_eq!(saved, "cf_clearance=abc");
+
+        let restored = ClaudeClient::new(
+            "sk-new",
+            "org",
+            &ApiEndpoints::default(),
+            &NetworkConfig::default(),
+        );
+        restored.import_cookies(&saved);
+        assert_eq!(restored.export_cookies(), "cf_clearance=abc");
+    }
+}
@@ -0,0 +1,153 @@
+//! Commands that run without the tray, e.g. `tokentorch status` for scripts and terminals.
+
+use crate::api::ClaudeClient;
+use crate::config::AppConfig;
+use crate::events::EventLog;
+use crate::history::History;
+use crate::poll::{self, PollContext};
+use crate::updater::APP_IDENTIFIER;
+use crate::{plan, usage};
+use serde_json::{Map, Value};
+use std::sync::Mutex;
+
+const HELP: &str = "\
+Usage: tokentorch [COMMAND]
+
+Commands:
+  status    Fetch usage once and print it (exit code 1 on error, 2 if not set up)
+  plan      Show how past windows would have gone on plans with more or less capacity
+
+Without a command, TokenTorch starts in the tray.";
+
+/// Run the command in `args` (without the program name) and return its exit code,
+/// or None when there's no command and the tray app should start.
+pub fn run(args: &[String]) -> Option<i32> {
+    let command = args.first()?.as_str();
+    if !matches!(command, "status" | "plan" | "help" | "--help" | "-h") {
+        return None;
+    }
+    attach_console();
+    match command {
+        "status" => Some(status()),
+        "plan" => Some(plan()),
+        _ => {
+            println!("{}", HELP);
+            Some(0)
+        }
+    }
+}
+
+/// Release builds on Windows use the GUI subsystem and start without a console, so output
+/// would go nowhere. Write to the console of the terminal we were started from instead.
+#[cfg(target_os = "windows")]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+    // Fails when there's no parent console, e.g. started from Explorer; nothing to print to then
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
+
+#[cfg(not(target_os = "windows"))]
+fn attach_console() {}
+
+fn status() -> i32 {
+    let config = load_config();
+    if !config.is_configured() {
+        eprintln!("TokenTorch isn't set up yet. Open the app and enter your session key.");
+        return 2;
+    }
+
+    let client = ClaudeClient::new(&config.session_key, &config.org_id, &config.api, &config.network);
+    if let Some(cookies) = read_store("state.json")
+        .and_then(|state| state.get("cookies").and_then(Value::as_str).map(str::to_string))
+    {
+        client.import_cookies(&cookies);
+    }
+
+    // The tray app owns the history and event files; work on copies so they aren't compacted
+    // or appended to twice
+    let context = PollContext {
+        history: Mutex::new(
+            History::default_path()
+                .map(|path| History::read_only(&path, &config.org_id))
+                .unwrap_or_default(),
+        ),
+        events: Mutex::new(
+            EventLog::default_path()
+                .map(|path| EventLog::read_only(&path, &config.org_id))
+                .unwrap_or_default(),
+        ),
+        ..PollContext::default()
+    };
+    let outcome = tauri::async_runtime::block_on(poll::poll_once(
+        &client,
+        &config.thresholds,
+        config.projection,
+        config.extra_usage_budget,
+        &context,
+    ));
+    // claude.ai may rotate the key on any request; keep the keychain copy current
+    if let Some(new_key) = &outcome.refreshed_session_key {
+        crate::save_session_key_to_keychain(new_key);
+    }
+
+    println!("{}", usage::status_text(&outcome.state));
+    for bar in outcome.state.bars() {
+        if let Some(stats) = &bar.stats_display {
+            println!("{}: {}", bar.label, stats);
+        }
+    }
+    if let Some(plan) = &outcome.state.plan {
+        println!("Plan: {}", plan.display);
+    }
+    if outcome.is_ok() {
+        0
+    } else {
+        1
+    }
+}
+
+/// Works offline from recorded history; no fetch.
+fn plan() -> i32 {
+    let config = load_config();
+    let history = History::default_path()
+        .map(|path| History::read_only(&path, &config.org_id))
+        .unwrap_or_default();
+    let Some(analysis) = plan::analyze(history.samples(&config.org_id), chrono::Utc::now()) else {
+        eprintln!("Not enough history yet. Leave TokenTorch running for a few days.");
+        return 2;
+    };
+
+    println!("{}\n", analysis.display);
+    println!("{:<10}{:>12}{:>14}{:>11}", "Capacity", "Limit hits", "Highest peak", "Avg. peak");
+    for s in &analysis.scenarios {
+        println!(
+            "{:<10}{:>12}{:>13.0}%{:>10.0}%",
+            format!("{}x", s.multiplier),
+            s.limit_hits,
+            s.highest_peak,
+            s.average_peak
+        );
+    }
+    println!("\nBased on {} windows over {:.0} days.", analysis.windows, analysis.days);
+    0
+}
+
+/// The tray app's saved settings and session key.
+fn load_config() -> AppConfig {
+    let mut config = AppConfig::default();
+    if let Some(stored) = read_store("config.json") {
+        config.apply_stored(|key| stored.get(key).cloned());
+    }
+    if let Some(key) = crate::load_session_key_from_keychain() {
+        config.session_key = key;
+    }
+    config
+}
+
+/// A store file the tray app keeps in its data directory, read directly.
+fn read_store(name: &str) -> Option<Map<String, Value>> {
+    let path = dirs::data_dir()?.join(APP_IDENTIFIER).join(name);
+    let text = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&text).ok()
+}
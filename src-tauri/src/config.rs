@@ -1,10 +1,135 @@
+use crate::api::{self, ApiEndpoints};
+use crate::hooks::HookSettings;
+use crate::mqtt::{self, MqttSettings};
+use crate::net::NetworkConfig;
+use crate::theme::ColorTheme;
+use crate::usage::{ProjectionMode, ThresholdPolicy};
+use crate::webhook::Webhook;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A named organization the session key has access to (e.g. personal vs team).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub org_id: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum BlinkMode {
+    Off,
+    Slow,
+    #[default]
+    Fast,
+}
+
+impl BlinkMode {
+    /// Time between on/off toggles of the tray icon, or None when blinking is disabled.
+    pub fn interval(self) -> Option<Duration> {
+        match self {
+            BlinkMode::Off => None,
+            BlinkMode::Slow => Some(Duration::from_millis(1200)),
+            BlinkMode::Fast => Some(Duration::from_millis(500)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlinkSettings {
+    pub mode: BlinkMode,
+    /// Blink this many times, then stay steady until the next escalation. None blinks indefinitely.
+    pub max_blinks: Option<u32>,
+    /// Clicking the tray icon while it blinks stops the blink until the next escalation.
+    pub stop_on_click: bool,
+    /// Stay steady when the OS "reduce motion" accessibility setting is on.
+    pub respect_reduced_motion: bool,
+}
+
+impl Default for BlinkSettings {
+    fn default() -> Self {
+        Self {
+            mode: BlinkMode::default(),
+            max_blinks: None,
+            stop_on_click: true,
+            respect_reduced_motion: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    /// Also offer prereleases (e.g. `0.7.0-beta.1`).
+    Beta,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdateSettings {
+    /// Off for offline / air-gapped machines: no update traffic at all.
+    pub enabled: bool,
+    pub channel: UpdateChannel,
+    /// Release the user chose to skip; newer releases are offered again.
+    pub skipped_version: Option<String>,
+    /// Static JSON release manifest to use instead of GitHub (e.g. on an internal server).
+    pub feed_url: Option<String>,
+}
+
+impl Default for UpdateSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            channel: UpdateChannel::default(),
+            skipped_version: None,
+            feed_url: None,
+        }
+    }
+}
+
+impl UpdateSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(url) = &self.feed_url {
+            let url = reqwest::Url::parse(url.trim())
+                .map_err(|e| format!("Invalid release feed URL: {}", e))?;
+            match url.scheme() {
+                "https" => {}
+                // The feed decides which version and asset get installed
+                "http" if api::is_loopback(&url) => {}
+                "http" => {
+                    return Err("Release feed URL must use https:// unless it's on this machine".to_string())
+                }
+                _ => return Err("Release feed URL must start with https://".to_string()),
+            }
+        }
+        Ok(())
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
     pub session_key: String,
     pub org_id: String,
     pub poll_interval_secs: u64,
+    pub profiles: Vec<Profile>,
+    pub theme: ColorTheme,
+    pub thresholds: ThresholdPolicy,
+    pub projection: ProjectionMode,
+    pub blink: BlinkSettings,
+    pub updates: UpdateSettings,
+    pub network: NetworkConfig,
+    pub api: ApiEndpoints,
+    /// Alert when extra-usage spend is projected past this many dollars in a month.
+    pub extra_usage_budget: Option<f64>,
+    /// Summarize the past week in a notification every Monday.
+    pub weekly_report: bool,
+    /// Team chat endpoints told about color changes and resets.
+    pub webhooks: Vec<Webhook>,
+    /// Local commands run on color changes and resets.
+    pub hooks: HookSettings,
+    /// Broker that bucket states are published to, for home-automation dashboards.
+    pub mqtt: MqttSettings,
 }
 
 impl Default for AppConfig {
@@ -13,6 +138,19 @@ impl Default for AppConfig {
             session_key: String::new(),
             org_id: String::new(),
             poll_interval_secs: 300, // 5 minutes
+            profiles: Vec::new(),
+            theme: ColorTheme::default(),
+            thresholds: ThresholdPolicy::default(),
+            projection: ProjectionMode::default(),
+            blink: BlinkSettings::default(),
+            updates: UpdateSettings::default(),
+            network: NetworkConfig::default(),
+            api: ApiEndpoints::default(),
+            extra_usage_budget: None,
+            weekly_report: false,
+            webhooks: Vec::new(),
+            hooks: HookSettings::default(),
+            mqtt: MqttSettings::default(),
         }
     }
 }
@@ -21,4 +159,164 @@ impl AppConfig {
     pub fn is_configured(&self) -> bool {
         !self.session_key.is_empty() && !self.org_id.is_empty()
     }
+
+    /// Add a profile for `org_id`, or rename the existing one.
+    /// An empty name keeps the existing name, or falls back to a short form of the org ID.
+    pub fn upsert_profile(&mut self, name: &str, org_id: &str) {
+        let name = name.trim();
+        if let Some(profile) = self.profiles.iter_mut().find(|p| p.org_id == org_id) {
+            if !name.is_empty() {
+                profile.name = name.to_string();
+            }
+            return;
+        }
+
+        let name = if name.is_empty() {
+            let short: String = org_id.chars().take(8).collect();
+            format!("Org {}", short)
+        } else {
+            name.to_string()
+        };
+        self.profiles.push(Profile {
+            name,
+            org_id: org_id.to_string(),
+        });
+    }
+
+    pub fn active_profile(&self) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.org_id == self.org_id)
+    }
+
+    /// Fill in non-secret settings saved under their store keys. Invalid values are logged
+    /// and left at their defaults.
+    pub fn apply_stored(&mut self, get: impl Fn(&str) -> Option<serde_json::Value>) {
+        if let Some(val) = get("org_id") {
+            if let Some(s) = val.as_str() {
+                self.org_id = s.to_string();
+            }
+        }
+        if let Some(val) = get("poll_interval_secs") {
+            if let Some(n) = val.as_u64() {
+                self.poll_interval_secs = n;
+            }
+        }
+        if let Some(val) = get("extra_usage_budget") {
+            self.extra_usage_budget = val.as_f64().filter(|b| *b > 0.0);
+        }
+        if let Some(val) = get("webhooks") {
+            match serde_json::from_value::<Vec<Webhook>>(val) {
+                Ok(hooks) if hooks.iter().all(|h| h.validate().is_ok()) => self.webhooks = hooks,
+                _ => eprintln!("[config] ignoring invalid webhooks"),
+            }
+        }
+        if let Some(val) = get("hooks") {
+            match serde_json::from_value::<HookSettings>(val) {
+                Ok(hooks) if hooks.validate().is_ok() => self.hooks = hooks,
+                _ => eprintln!("[config] ignoring invalid hooks"),
+            }
+        }
+        if let Some(val) = get("mqtt") {
+            match serde_json::from_value::<MqttSettings>(val) {
+                Ok(mut settings) if settings.validate().is_ok() => {
+                    // Every machine used to share this default and kick the others off the broker
+                    if settings.client_id == "tokentorch" {
+                        settings.client_id = mqtt::default_client_id();
+                    }
+                    self.mqtt = settings;
+                }
+                _ => eprintln!("[config] ignoring invalid MQTT settings"),
+            }
+        }
+        if let Some(val) = get("weekly_report") {
+            if let Some(enabled) = val.as_bool() {
+                self.weekly_report = enabled;
+            }
+        }
+        if let Some(val) = get("profiles") {
+            if let Ok(profiles) = serde_json::from_value(val) {
+                self.profiles = profiles;
+            }
+        }
+        if let Some(val) = get("theme") {
+            if let Ok(theme) = serde_json::from_value(val) {
+                self.theme = theme;
+            }
+        }
+        if let Some(val) = get("thresholds") {
+            match serde_json::from_value::<ThresholdPolicy>(val) {
+                Ok(thresholds) if thresholds.validate().is_ok() => {
+                    self.thresholds = thresholds;
+                }
+                _ => eprintln!("[config] ignoring invalid thresholds, using defaults"),
+            }
+        }
+        // Saved with the thresholds before it had a key of its own
+        let projection = get("projection").or_else(|| get("thresholds")?.get("projection").cloned());
+        if let Some(val) = projection {
+            if let Ok(projection) = serde_json::from_value(val) {
+                self.projection = projection;
+            }
+        }
+        if let Some(val) = get("blink") {
+            if let Ok(blink) = serde_json::from_value(val) {
+                self.blink = blink;
+            }
+        }
+        if let Some(val) = get("updates") {
+            match serde_json::from_value::<UpdateSettings>(val) {
+                Ok(updates) if updates.validate().is_ok() => self.updates = updates,
+                _ => eprintln!("[config] ignoring invalid update settings, using defaults"),
+            }
+        }
+        if let Some(val) = get("network") {
+            match serde_json::from_value::<NetworkConfig>(val) {
+                Ok(network) if network.validate_structure().is_ok() => self.network = network,
+                _ => eprintln!("[config] ignoring invalid network settings, using defaults"),
+            }
+        }
+        if let Some(val) = get("api") {
+            match serde_json::from_value::<ApiEndpoints>(val) {
+                Ok(api) if api.validate().is_ok() => self.api = api,
+                _ => eprintln!("[config] ignoring invalid API endpoints, using defaults"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_feed_needs_https_unless_on_this_machine() {
+        let feed = |url: &str| UpdateSettings {
+            feed_url: Some(url.to_string()),
+            ..UpdateSettings::default()
+        };
+        assert!(feed("https://intranet.example/tokentorch/latest.json").validate().is_ok());
+        assert!(feed("http://127.0.0.1:8080/latest.json").validate().is_ok());
+        assert!(feed("http://localhost/latest.json").validate().is_ok());
+        assert!(feed("http://intranet.example/tokentorch/latest.json").validate().is_err());
+        assert!(feed("ftp://intranet.example/latest.json").validate().is_err());
+        assert!(UpdateSettings::default().validate().is_ok());
+    }
+
+    #[test]
+    fn projection_has_its_own_key_and_survives_invalid_thresholds() {
+        let load = |stored: serde_json::Value| {
+            let mut config = AppConfig::default();
+            config.apply_stored(|key| stored.get(key).cloned());
+            config
+        };
+        let config = load(serde_json::json!({
+            "projection": "Learned",
+            "thresholds": { "confidence": 2.0 },
+        }));
+        assert_eq!(config.projection, ProjectionMode::Learned);
+        assert_eq!(config.thresholds, ThresholdPolicy::default());
+
+        // Older stores kept it inside the thresholds
+        let legacy = load(serde_json::json!({ "thresholds": { "projection": "Learned" } }));
+        assert_eq!(legacy.projection, ProjectionMode::Learned);
+    }
 }
@@ -0,0 +1,334 @@
+//! Window resets and limit hits, detected from successive polls of an org and kept in
+//! `events.jsonl`.
+
+use crate::alerts::same_window;
+use crate::history::{self, History, Sample};
+use crate::updater::APP_IDENTIFIER;
+use crate::usage::{ApiUsageResponse, BucketKind, UsageState};
+use chrono::{DateTime, Datelike, Duration, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Long enough for month-over-month comparisons. Older events are dropped when the log is
+/// opened and as new polls are observed.
+const RETENTION_DAYS: i64 = 400;
+/// Utilization falling by more than this within one window also counts as a reset.
+const RESET_DROP_POINTS: f64 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum EventKind {
+    /// A window ended; `peak` is the highest utilization polled in it.
+    Reset { peak: f64 },
+    /// Utilization reached 100%.
+    LimitHit,
+}
+
+/// `at` is when the poll noticed the event, which may be later than it happened if the
+/// app wasn't running.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub at: DateTime<Utc>,
+    /// Empty in lines written before events carried their org.
+    #[serde(default)]
+    pub org_id: String,
+    pub kind: BucketKind,
+    #[serde(flatten)]
+    pub event: EventKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EventStats {
+    /// Peak of the most recent finished window.
+    pub last_peak: Option<f64>,
+    pub limit_hits_this_month: usize,
+}
+
+impl EventStats {
+    /// E.g. "last window peaked at 87% · hit the limit 3 times this month".
+    pub fn display(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(peak) = self.last_peak {
+            parts.push(format!("last window peaked at {:.0}%", peak));
+        }
+        match self.limit_hits_this_month {
+            0 => {}
+            1 => parts.push("hit the limit once this month".to_string()),
+            n => parts.push(format!("hit the limit {} times this month", n)),
+        }
+        (!parts.is_empty()).then(|| parts.join(" · "))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct EventLog {
+    /// Where events are appended; None keeps them in memory only.
+    path: Option<PathBuf>,
+    events: Vec<Event>,
+}
+
+impl EventLog {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER).join("events.jsonl"))
+    }
+
+    /// Load `path`, skipping unreadable lines and dropping expired events. Events without an
+    /// org are taken to be `org_id`'s, the org the app is set up with, and saved as such.
+    pub fn open(path: PathBuf, org_id: &str) -> Self {
+        let (events, changed) = load(&path, org_id);
+        if changed {
+            let tmp = path.with_extension("jsonl.tmp");
+            let _ = std::fs::remove_file(&tmp);
+            let compacted = history::append(&tmp, &events).and_then(|()| std::fs::rename(&tmp, &path));
+            if let Err(e) = compacted {
+                eprintln!("[events] compacting failed: {}", e);
+            }
+        }
+        Self {
+            path: Some(path),
+            events,
+        }
+    }
+
+    /// Load `path` like `open`, but leave the file alone and keep new events in memory, for
+    /// the CLI running next to the tray app.
+    pub fn read_only(path: &Path, org_id: &str) -> Self {
+        Self {
+            path: None,
+            events: load(path, org_id).0,
+        }
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Compare a successful poll of `org_id` with its last one in `history` (call before
+    /// recording it there) and keep any resets or limit hits. Returns the new events.
+    pub fn observe(
+        &mut self,
+        history: &History,
+        org_id: &str,
+        response: &ApiUsageResponse,
+        at: DateTime<Utc>,
+    ) -> Vec<Event> {
+        let cutoff = at - Duration::days(RETENTION_DAYS);
+        self.events.retain(|e| e.at >= cutoff);
+
+        let new = detect(history.samples(org_id), org_id, response, at);
+        if new.is_empty() {
+            return new;
+        }
+        for event in &new {
+            eprintln!("[events] {} {:?}", event.kind.label(), event.event);
+        }
+        if let Some(path) = &self.path {
+            if let Err(e) = history::append(path, &new) {
+                eprintln!("[events] append failed: {}", e);
+            }
+        }
+        self.events.extend(new.iter().cloned());
+        new
+    }
+
+    pub fn stats(&self, org_id: &str, kind: BucketKind, now: DateTime<Utc>) -> EventStats {
+        let month_start = now
+            .with_timezone(&Local)
+            .date_naive()
+            .with_day(1)
+            .and_then(|day| day.and_hms_opt(0, 0, 0))
+            .and_then(|start| start.and_local_timezone(Local).earliest())
+            .map(|start| start.with_timezone(&Utc));
+        let of_kind = || {
+            self.events
+                .iter()
+                .filter(move |e| e.org_id == org_id && e.kind == kind)
+        };
+
+        EventStats {
+            last_peak: of_kind().rev().find_map(|e| match e.event {
+                EventKind::Reset { peak } => Some(peak),
+                EventKind::LimitHit => None,
+            }),
+            limit_hits_this_month: of_kind()
+                .filter(|e| e.event == EventKind::LimitHit && month_start.is_some_and(|m| e.at >= m))
+                .count(),
+        }
+    }
+
+    /// Fill in each bar's event statistics for `org_id`.
+    pub fn apply(&self, org_id: &str, state: &mut UsageState, now: DateTime<Utc>) {
+        for bar in state.bars_mut() {
+            bar.stats_display = self.stats(org_id, bar.kind, now).display();
+        }
+    }
+}
+
+/// The readable, unexpired events in `path`, oldest first, and whether the file needs
+/// rewriting to match.
+fn load(path: &Path, org_id: &str) -> (Vec<Event>, bool) {
+    let (mut events, total) = history::read_lines::<Event>(path);
+    let cutoff = Utc::now() - Duration::days(RETENTION_DAYS);
+    events.retain(|e| e.at >= cutoff);
+    events.sort_by_key(|e| e.at);
+
+    let mut changed = events.len() != total;
+    for event in events.iter_mut().filter(|e| e.org_id.is_empty()) {
+        event.org_id = org_id.to_string();
+        changed = true;
+    }
+    (events, changed)
+}
+
+/// `samples` are `org_id`'s, so a profile switch isn't mistaken for a reset.
+fn detect(samples: &[Sample], org_id: &str, response: &ApiUsageResponse, at: DateTime<Utc>) -> Vec<Event> {
+    let mut events = Vec::new();
+    for kind in [BucketKind::Session, BucketKind::Weekly] {
+        let Some(bucket) = response.bucket(kind) else {
+            continue;
+        };
+        let previous: Vec<&Sample> = samples.iter().filter(|s| s.kind == kind).collect();
+        let mut push = |event| {
+            events.push(Event {
+                at,
+                org_id: org_id.to_string(),
+                kind,
+                event,
+            })
+        };
+
+        let was = match previous.last() {
+            Some(last)
+                if !same_window(&last.resets_at, &bucket.resets_at)
+                    || bucket.utilization < last.utilization - RESET_DROP_POINTS =>
+            {
+                let peak = previous
+                    .iter()
+                    .rev()
+                    .take_while(|s| same_window(&s.resets_at, &last.resets_at))
+                    .map(|s| s.utilization)
+                    .fold(0.0, f64::max);
+                push(EventKind::Reset { peak });
+                0.0
+            }
+            Some(last) => last.utilization,
+            None => 0.0,
+        };
+        if bucket.utilization >= 100.0 && was < 100.0 {
+            push(EventKind::LimitHit);
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::response;
+
+    /// Feed polls through an event log and history the way `poll_once` does, an hour apart
+    /// and ending just before `end`.
+    fn replay(polls: &[(f64, &str)], log: &mut EventLog, end: DateTime<Utc>) {
+        let mut history = History::default();
+        let start = end - Duration::hours(polls.len() as i64);
+        for (i, (used, resets_at)) in polls.iter().enumerate() {
+            let at = start + Duration::hours(i as i64);
+            let response = response(*used, resets_at);
+            log.observe(&history, "org", &response, at);
+            history.record("org", &response, at);
+        }
+    }
+
+    #[test]
+    fn detects_resets_and_limit_hits() {
+        const FIRST: &str = "2026-01-16T09:00:00Z";
+        const SECOND: &str = "2026-01-16T14:00:00Z";
+        let mut log = EventLog::default();
+        replay(
+            &[
+                (40.0, FIRST),
+                (87.0, FIRST),
+                (87.0, "2026-01-16T09:00:30Z"),
+                (5.0, SECOND),
+                (100.0, SECOND),
+                (100.0, SECOND),
+                // Same reset time, but usage fell: an early reset
+                (2.0, SECOND),
+                (100.0, SECOND),
+            ],
+            &mut log,
+            Utc::now(),
+        );
+
+        let kinds: Vec<EventKind> = log.events().iter().map(|e| e.event).collect();
+        assert_eq!(
+            kinds,
+            [
+                EventKind::Reset { peak: 87.0 },
+                EventKind::LimitHit,
+                EventKind::Reset { peak: 100.0 },
+                EventKind::LimitHit,
+            ]
+        );
+        assert_eq!(log.stats("org", BucketKind::Weekly, Utc::now()), EventStats::default());
+
+        // Switching to another org isn't a reset of this one's window
+        let mut history = History::default();
+        history.record("org", &response(90.0, FIRST), Utc::now());
+        assert!(log.observe(&history, "other", &response(5.0, SECOND), Utc::now()).is_empty());
+    }
+
+    #[test]
+    fn stats_survive_reopen_and_count_this_month() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        // Noon on the 15th, so the replayed polls fall in this month wherever the test runs
+        let now = Local::now()
+            .date_naive()
+            .with_day(15)
+            .and_then(|day| day.and_hms_opt(12, 0, 0))
+            .and_then(|noon| noon.and_local_timezone(Local).earliest())
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let mut log = EventLog::open(path.clone(), "org");
+        replay(&[(60.0, "2026-01-16T09:00:00Z"), (100.0, "2026-01-16T09:00:00Z")], &mut log, now);
+        replay(&[(100.0, "2026-01-16T14:00:00Z"), (3.0, "2026-01-16T19:00:00Z")], &mut log, now);
+        // An old hit from a previous month, and one from another org
+        history::append(
+            &path,
+            &[
+                Event {
+                    at: now - Duration::days(45),
+                    org_id: "org".to_string(),
+                    kind: BucketKind::Session,
+                    event: EventKind::LimitHit,
+                },
+                Event {
+                    at: now - Duration::hours(1),
+                    org_id: "other".to_string(),
+                    kind: BucketKind::Session,
+                    event: EventKind::LimitHit,
+                },
+            ],
+        )
+        .unwrap();
+
+        let reopened = EventLog::open(path, "org");
+        let stats = reopened.stats("org", BucketKind::Session, now);
+        assert_eq!(stats.last_peak, Some(100.0));
+        assert_eq!(stats.limit_hits_this_month, 2);
+        assert_eq!(
+            stats.display().as_deref(),
+            Some("last window peaked at 100% · hit the limit 2 times this month")
+        );
+
+        // A long-running app drops expired events as it observes new polls: only the hit
+        // from 45 days before `now` is past retention by `later`
+        let mut log = reopened;
+        assert_eq!(log.events().len(), 5);
+        let later = now + Duration::days(RETENTION_DAYS - 30);
+        log.observe(&History::default(), "org", &response(1.0, "2026-01-16T09:00:00Z"), later);
+        assert_eq!(log.events().len(), 4);
+    }
+}
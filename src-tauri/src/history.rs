@@ -0,0 +1,288 @@
+//! Usage samples over time, one JSON line per bucket per successful poll, kept apart per org
+//! so switching profiles doesn't mix one account's usage into another's.
+
+use crate::alerts::same_window;
+use crate::updater::APP_IDENTIFIER;
+use crate::usage::{online_seconds_between, ApiUsageResponse, BucketKind};
+use chrono::{DateTime, Duration, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Samples older than this are dropped when the history is opened and as new ones come in.
+const RETENTION_DAYS: i64 = 28;
+/// Burn-rate spread only looks at the last week, so it follows changes in work habits.
+const SPREAD_LOOKBACK_DAYS: i64 = 7;
+/// Intervals shorter than this (in online time) are too noisy to count.
+const MIN_INTERVAL_ONLINE_SECONDS: f64 = 5.0 * 60.0;
+/// Fewer rate intervals than this and we stick with the default spread.
+const MIN_SPREAD_INTERVALS: usize = 6;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub at: DateTime<Utc>,
+    /// Empty in lines written before samples carried their org.
+    #[serde(default)]
+    pub org_id: String,
+    pub kind: BucketKind,
+    pub utilization: f64,
+    pub resets_at: String,
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    /// Where samples are appended; None keeps them in memory only.
+    path: Option<PathBuf>,
+    /// Oldest first, by org ID.
+    samples: HashMap<String, Vec<Sample>>,
+}
+
+impl History {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER).join("history.jsonl"))
+    }
+
+    /// Load `path`, skipping unreadable lines and dropping expired samples. Samples without
+    /// an org are taken to be `org_id`'s, the org the app is set up with, and saved as such.
+    pub fn open(path: PathBuf, org_id: &str) -> Self {
+        let (mut history, changed) = Self::load(&path, org_id);
+        history.path = Some(path);
+        if changed {
+            if let Err(e) = history.rewrite() {
+                eprintln!("[history] compacting failed: {}", e);
+            }
+        }
+        history
+    }
+
+    /// Load `path` like `open`, but leave the file alone and keep new samples in memory, for
+    /// the CLI running next to the tray app.
+    pub fn read_only(path: &Path, org_id: &str) -> Self {
+        Self::load(path, org_id).0
+    }
+
+    /// The readable, unexpired samples in `path`, and whether the file needs rewriting to match.
+    fn load(path: &Path, org_id: &str) -> (Self, bool) {
+        let (mut loaded, total) = read_lines::<Sample>(path);
+
+        let cutoff = Utc::now() - Duration::days(RETENTION_DAYS);
+        loaded.retain(|s| s.at >= cutoff);
+        loaded.sort_by_key(|s| s.at);
+        let mut changed = loaded.len() != total;
+        let mut samples: HashMap<String, Vec<Sample>> = HashMap::new();
+        for mut sample in loaded {
+            if sample.org_id.is_empty() {
+                sample.org_id = org_id.to_string();
+                changed = true;
+            }
+            samples.entry(sample.org_id.clone()).or_default().push(sample);
+        }
+        (Self { path: None, samples }, changed)
+    }
+
+    /// The samples recorded for `org_id`, oldest first.
+    pub fn samples(&self, org_id: &str) -> &[Sample] {
+        self.samples.get(org_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Record the session and weekly buckets of a successful poll for `org_id`.
+    pub fn record(&mut self, org_id: &str, response: &ApiUsageResponse, at: DateTime<Utc>) {
+        let new: Vec<Sample> = [BucketKind::Session, BucketKind::Weekly]
+            .into_iter()
+            .filter_map(|kind| {
+                response.bucket(kind).map(|b| Sample {
+                    at,
+                    org_id: org_id.to_string(),
+                    kind,
+                    utilization: b.utilization,
+                    resets_at: b.resets_at.clone(),
+                })
+            })
+            .collect();
+        if let Some(path) = &self.path {
+            if let Err(e) = append(path, &new) {
+                eprintln!("[history] append failed: {}", e);
+            }
+        }
+        self.samples.entry(org_id.to_string()).or_default().extend(new);
+
+        let cutoff = at - Duration::days(RETENTION_DAYS);
+        for samples in self.samples.values_mut() {
+            samples.retain(|s| s.at >= cutoff);
+        }
+    }
+
+    /// Std dev / mean of `org_id`'s per-online-hour burn rate between consecutive samples of
+    /// `kind` over the last week, or None without enough data.
+    pub fn rate_spread(&self, org_id: &str, kind: BucketKind, now: DateTime<Utc>) -> Option<f64> {
+        let since = now - Duration::days(SPREAD_LOOKBACK_DAYS);
+        let samples: Vec<&Sample> = self
+            .samples(org_id)
+            .iter()
+            .filter(|s| s.kind == kind && s.at >= since)
+            .collect();
+
+        let rates: Vec<f64> = samples
+            .windows(2)
+            .filter(|pair| same_window(&pair[0].resets_at, &pair[1].resets_at))
+            .filter_map(|pair| {
+                let online = online_seconds_between(pair[0].at, pair[1].at);
+                let used = pair[1].utilization - pair[0].utilization;
+                (online >= MIN_INTERVAL_ONLINE_SECONDS && used >= 0.0)
+                    .then(|| used / (online / 3600.0))
+            })
+            .collect();
+        if rates.len() < MIN_SPREAD_INTERVALS {
+            return None;
+        }
+
+        let mean = rates.iter().sum::<f64>() / rates.len() as f64;
+        if mean <= 0.0 {
+            return None;
+        }
+        let variance = rates.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / rates.len() as f64;
+        Some((variance.sqrt() / mean).clamp(0.1, 3.0))
+    }
+
+    fn rewrite(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let tmp = path.with_extension("jsonl.tmp");
+        let _ = std::fs::remove_file(&tmp);
+        let all: Vec<&Sample> = self.samples.values().flatten().collect();
+        append(&tmp, &all)?;
+        std::fs::rename(tmp, path)
+    }
+}
+
+/// Samples of `kind` split into windows, oldest first.
+pub(crate) fn group_windows(samples: &[Sample], kind: BucketKind) -> Vec<Vec<&Sample>> {
+    let mut windows: Vec<Vec<&Sample>> = Vec::new();
+    for sample in samples.iter().filter(|s| s.kind == kind) {
+        match windows.last_mut() {
+            Some(window) if same_window(&window[0].resets_at, &sample.resets_at) => window.push(sample),
+            _ => windows.push(vec![sample]),
+        }
+    }
+    windows
+}
+
+/// The readable records of a JSON-lines file, and how many non-empty lines it had.
+pub(crate) fn read_lines<T: DeserializeOwned>(path: &Path) -> (Vec<T>, usize) {
+    let text = std::fs::read_to_string(path).unwrap_or_default();
+    let records = text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    (records, text.lines().filter(|l| !l.trim().is_empty()).count())
+}
+
+pub(crate) fn append<T: Serialize>(path: &Path, records: &[T]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    for record in records {
+        let line = serde_json::to_string(record).map_err(std::io::Error::other)?;
+        writeln!(file, "{}", line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::response;
+
+    #[test]
+    fn samples_survive_reopen_and_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let now = Utc::now();
+
+        let mut history = History::open(path.clone(), "org");
+        history.record("org", &response(10.0, "2026-01-16T09:00:00Z"), now - Duration::days(40));
+        history.record("org", &response(20.0, "2026-01-16T09:00:00Z"), now);
+        // A long-running app drops expired samples as it records new ones
+        assert_eq!(history.samples("org").len(), 1);
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+
+        // A read-only copy neither compacts the file nor appends to it
+        let mut copy = History::read_only(&path, "org");
+        copy.record("org", &response(30.0, "2026-01-16T09:00:00Z"), now);
+        assert_eq!(copy.samples("org").len(), 2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+
+        let reopened = History::open(path.clone(), "org");
+        assert_eq!(reopened.samples("org").len(), 1);
+        assert_eq!(reopened.samples("org")[0].utilization, 20.0);
+        // Expired and broken lines were compacted away
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn orgs_keep_separate_samples_and_old_lines_join_the_current_org() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let now = Utc::now();
+        std::fs::write(
+            &path,
+            format!(
+                "{{\"at\":\"{}\",\"kind\":\"Session\",\"utilization\":30.0,\"resets_at\":\"2026-01-16T09:00:00Z\"}}\n",
+                now.to_rfc3339()
+            ),
+        )
+        .unwrap();
+
+        let mut history = History::open(path.clone(), "personal");
+        history.record("team", &response(80.0, "2026-01-16T11:00:00Z"), now);
+        assert_eq!(history.samples("personal")[0].utilization, 30.0);
+        assert_eq!(history.samples("team").len(), 1);
+        assert!(history.samples("other").is_empty());
+
+        // The old line was saved with its org, so it stays put after switching profiles
+        let reopened = History::open(path, "team");
+        assert_eq!(reopened.samples("personal").len(), 1);
+        assert_eq!(reopened.samples("team")[0].utilization, 80.0);
+    }
+
+    #[test]
+    fn steady_usage_has_less_spread_than_bursty_usage() {
+        // Hourly samples from 9:00 to 17:00 local on one day, all in the same window
+        let day = chrono::Local::now().date_naive() - chrono::Days::new(1);
+        let at = |hour: u32| {
+            day.and_hms_opt(hour, 0, 0)
+                .unwrap()
+                .and_local_timezone(chrono::Local)
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        let resets_at = (at(9) + Duration::days(5)).to_rfc3339();
+        let spread = |increments: &[f64]| {
+            let mut history = History::default();
+            let mut used = 0.0;
+            for (i, inc) in increments.iter().enumerate() {
+                used += inc;
+                history.record("org", &response(used, &resets_at), at(9 + i as u32));
+            }
+            history.rate_spread("org", BucketKind::Session, at(18))
+        };
+
+        let steady = spread(&[0.0, 4.0, 5.0, 4.0, 5.0, 4.0, 5.0, 4.0]).unwrap();
+        let bursty = spread(&[0.0, 0.0, 12.0, 0.0, 1.0, 14.0, 0.0, 4.0]).unwrap();
+        assert!(steady < 0.2, "steady spread {}", steady);
+        assert!(bursty > 1.0, "bursty spread {}", bursty);
+        assert_eq!(spread(&[0.0, 4.0, 5.0]), None);
+    }
+}
@@ -0,0 +1,398 @@
+//! User commands run on usage events, e.g. to pause CI jobs while the weekly bar is red.
+//!
+//! The event goes to the command as `TOKENTORCH_*` environment variables and as JSON on stdin.
+
+use crate::webhook::{Trigger, WebhookEvent};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+
+/// Output kept in the log per stream; hooks can be chatty.
+const MAX_LOGGED_OUTPUT: usize = 2000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandHook {
+    pub enabled: bool,
+    /// Run through the shell (`sh -c`, or `cmd /C` on Windows).
+    pub command: String,
+    /// The command is killed after this long.
+    pub timeout_secs: u64,
+    /// Any color change, up or down, so a hook can undo what it did on the way up.
+    pub on_color_change: bool,
+    pub on_reset: bool,
+}
+
+impl Default for CommandHook {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            command: String::new(),
+            timeout_secs: 30,
+            on_color_change: true,
+            on_reset: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HookSettings {
+    pub hooks: Vec<CommandHook>,
+    /// At most this many commands run at once; the rest wait their turn.
+    pub max_concurrent: usize,
+}
+
+impl Default for HookSettings {
+    fn default() -> Self {
+        Self {
+            hooks: Vec::new(),
+            max_concurrent: 2,
+        }
+    }
+}
+
+impl HookSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=8).contains(&self.max_concurrent) {
+            return Err("Run between 1 and 8 hooks at once".to_string());
+        }
+        self.hooks.iter().try_for_each(CommandHook::validate)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HookOutcome {
+    pub command: String,
+    /// None if the command couldn't start, timed out or was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl HookOutcome {
+    pub fn is_ok(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// One line for the log or the settings "Test" button.
+    pub fn summary(&self) -> String {
+        let status = match (&self.error, self.timed_out, self.exit_code) {
+            (Some(err), _, _) => err.clone(),
+            (None, true, _) => "timed out".to_string(),
+            (None, false, Some(code)) => format!("exited with {}", code),
+            (None, false, None) => "killed".to_string(),
+        };
+        format!("{} ({} ms)", status, self.duration_ms)
+    }
+}
+
+impl CommandHook {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.command.trim().is_empty() {
+            return Err("Hook command can't be empty".to_string());
+        }
+        if !(1..=600).contains(&self.timeout_secs) {
+            return Err("Hook timeout must be between 1 and 600 seconds".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn wants(&self, event: &WebhookEvent) -> bool {
+        self.enabled
+            && match event.trigger {
+                Trigger::ColorChange { .. } => self.on_color_change,
+                Trigger::Reset { .. } => self.on_reset,
+                Trigger::Test => true,
+            }
+    }
+
+    /// Run the command for `event` and wait for it, up to the timeout.
+    pub async fn run(&self, event: &WebhookEvent) -> HookOutcome {
+        let started = Instant::now();
+        let mut outcome = HookOutcome {
+            command: self.command.clone(),
+            exit_code: None,
+            timed_out: false,
+            stdout: String::new(),
+            stderr: String::new(),
+            error: None,
+            duration_ms: 0,
+        };
+
+        let mut command = shell(&self.command);
+        command
+            .envs(env_vars(event))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        command.process_group(0);
+        match command.spawn() {
+            Err(e) => outcome.error = Some(format!("failed to start: {}", e)),
+            Ok(mut child) => {
+                let group = ProcessGroup::attach(&child);
+                if let Some(mut stdin) = child.stdin.take() {
+                    let payload = serde_json::to_vec(event).unwrap_or_default();
+                    // A command that ignores stdin may exit before reading it; that's fine
+                    let _ = stdin.write_all(&payload).await;
+                }
+                let timeout = Duration::from_secs(self.timeout_secs);
+                // On timeout the child is dropped, which kills the shell; the group takes
+                // whatever it started along
+                match tokio::time::timeout(timeout, child.wait_with_output()).await {
+                    Err(_) => {
+                        outcome.timed_out = true;
+                        if let Some(group) = &group {
+                            group.kill();
+                        }
+                    }
+                    Ok(Err(e)) => outcome.error = Some(e.to_string()),
+                    Ok(Ok(output)) => {
+                        outcome.exit_code = output.status.code();
+                        outcome.stdout = truncated(&output.stdout);
+                        outcome.stderr = truncated(&output.stderr);
+                    }
+                }
+            }
+        }
+        outcome.duration_ms = started.elapsed().as_millis() as u64;
+        outcome
+    }
+}
+
+/// Run every hook that wants each event, `slots` at a time, logging the results.
+pub async fn run_all(
+    hooks: &[CommandHook],
+    events: &[WebhookEvent],
+    slots: Arc<Semaphore>,
+) -> Vec<HookOutcome> {
+    let mut tasks = tokio::task::JoinSet::new();
+    for event in events {
+        for hook in hooks.iter().filter(|h| h.wants(event)) {
+            let (hook, event, slots) = (hook.clone(), event.clone(), slots.clone());
+            tasks.spawn(async move {
+                let _slot = slots.acquire_owned().await;
+                let outcome = hook.run(&event).await;
+                log(&outcome);
+                outcome
+            });
+        }
+    }
+    let mut outcomes = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        match result {
+            Ok(outcome) => outcomes.push(outcome),
+            Err(e) => eprintln!("[hooks] task failed: {}", e),
+        }
+    }
+    outcomes
+}
+
+fn log(outcome: &HookOutcome) {
+    eprintln!("[hooks] {}: {}", outcome.command, outcome.summary());
+    if !outcome.is_ok() && !outcome.stderr.trim().is_empty() {
+        eprintln!("[hooks] stderr: {}", outcome.stderr.trim());
+    }
+}
+
+fn env_vars(event: &WebhookEvent) -> Vec<(&'static str, String)> {
+    let name = |value: &dyn std::fmt::Debug| format!("{:?}", value).to_lowercase();
+    let mut vars = vec![
+        (
+            "TOKENTORCH_EVENT",
+            match event.trigger {
+                Trigger::ColorChange { .. } => "color_change".to_string(),
+                Trigger::Reset { .. } => "reset".to_string(),
+                Trigger::Test => "test".to_string(),
+            },
+        ),
+        ("TOKENTORCH_PROFILE", event.profile.clone()),
+        ("TOKENTORCH_BUCKET", name(&event.kind)),
+        ("TOKENTORCH_COLOR", name(&event.color)),
+        (
+            "TOKENTORCH_UTILIZATION",
+            format!("{:.1}", event.utilization),
+        ),
+        ("TOKENTORCH_PROJECTED", format!("{:.1}", event.projected)),
+        ("TOKENTORCH_RESETS_AT", event.resets_at.clone()),
+    ];
+    match event.trigger {
+        Trigger::ColorChange { from, .. } => vars.push(("TOKENTORCH_PREVIOUS_COLOR", name(&from))),
+        Trigger::Reset { peak } => vars.push(("TOKENTORCH_PEAK", format!("{:.1}", peak))),
+        Trigger::Test => {}
+    }
+    vars
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut c = Command::new("cmd");
+    // cmd.exe parses its own command line; the usual argument quoting would mangle any
+    // quotes in the command, e.g. around "C:\Program Files\..."
+    c.arg("/C").raw_arg(command);
+    c
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut c = Command::new("sh");
+    c.arg("-c").arg(command);
+    c
+}
+
+/// The processes a hook started: its own process group on Unix, a job object on Windows.
+/// Killing only the shell would leave e.g. `sleep 600 | tee log` running and holding the
+/// output pipes open.
+struct ProcessGroup {
+    #[cfg(unix)]
+    pgid: i32,
+    #[cfg(windows)]
+    job: windows_sys::Win32::Foundation::HANDLE,
+}
+
+// The job handle is only used to kill and close the job
+#[cfg(windows)]
+unsafe impl Send for ProcessGroup {}
+
+impl ProcessGroup {
+    /// The group the child leads, having been started with `process_group(0)`.
+    #[cfg(unix)]
+    fn attach(child: &tokio::process::Child) -> Option<Self> {
+        let pgid = i32::try_from(child.id()?).ok()?;
+        Some(Self { pgid })
+    }
+
+    /// Put the child in a new job; processes it starts from then on join it too. Anything it
+    /// managed to start before this is missed.
+    #[cfg(windows)]
+    fn attach(child: &tokio::process::Child) -> Option<Self> {
+        use windows_sys::Win32::System::JobObjects::{AssignProcessToJobObject, CreateJobObjectW};
+
+        let process = child.raw_handle()?;
+        let job = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+        if job.is_null() {
+            return None;
+        }
+        let group = Self { job };
+        (unsafe { AssignProcessToJobObject(job, process) } != 0).then_some(group)
+    }
+
+    fn kill(&self) {
+        #[cfg(unix)]
+        if unsafe { libc::killpg(self.pgid, libc::SIGKILL) } != 0 {
+            eprintln!("[hooks] killing process group failed: {}", std::io::Error::last_os_error());
+        }
+        #[cfg(windows)]
+        if unsafe { windows_sys::Win32::System::JobObjects::TerminateJobObject(self.job, 1) } == 0 {
+            eprintln!("[hooks] terminating job failed: {}", std::io::Error::last_os_error());
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        unsafe { windows_sys::Win32::Foundation::CloseHandle(self.job) };
+    }
+}
+
+fn truncated(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    match text.char_indices().nth(MAX_LOGGED_OUTPUT) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.into_owned(),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use crate::usage::UsageColor;
+
+    fn event() -> WebhookEvent {
+        WebhookEvent {
+            color: UsageColor::RedBlink,
+            utilization: 97.25,
+            projected: 140.0,
+            ..test_support::event(Trigger::ColorChange {
+                from: UsageColor::Yellow,
+                to: UsageColor::RedBlink,
+            })
+        }
+    }
+
+    fn hook(command: &str) -> CommandHook {
+        CommandHook {
+            command: command.to_string(),
+            timeout_secs: 1,
+            ..CommandHook::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn command_gets_event_in_env_and_stdin() {
+        let outcome = hook(
+            "echo \"$TOKENTORCH_BUCKET $TOKENTORCH_PREVIOUS_COLOR->$TOKENTORCH_COLOR $TOKENTORCH_UTILIZATION\"; cat",
+        )
+        .run(&event())
+        .await;
+        assert!(outcome.is_ok(), "{:?}", outcome);
+        let mut lines = outcome.stdout.lines();
+        assert_eq!(lines.next(), Some("weekly yellow->redblink 97.2"));
+        let stdin: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(stdin["kind"], "Weekly");
+        assert_eq!(stdin["projected"], 140.0);
+
+        let failed = hook("echo nope >&2; exit 3").run(&event()).await;
+        assert_eq!(failed.exit_code, Some(3));
+        assert_eq!(failed.stderr, "nope\n");
+        assert!(failed.summary().starts_with("exited with 3"));
+    }
+
+    #[tokio::test]
+    async fn slow_commands_time_out_and_slots_limit_concurrency() {
+        let started = Instant::now();
+        let outcome = hook("sleep 5").run(&event()).await;
+        assert!(outcome.timed_out);
+        assert!(started.elapsed() < Duration::from_secs(3));
+
+        // Two 300 ms hooks through one slot run back to back
+        let hooks = [hook("sleep 0.3"), hook("sleep 0.3")];
+        let started = Instant::now();
+        let outcomes = run_all(&hooks, &[event()], Arc::new(Semaphore::new(1))).await;
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.iter().all(HookOutcome::is_ok));
+        assert!(started.elapsed() >= Duration::from_millis(600));
+
+        let off = CommandHook {
+            enabled: false,
+            ..hook("true")
+        };
+        assert!(run_all(&[off], &[event()], Arc::new(Semaphore::new(1)))
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn timeout_kills_what_the_command_started() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("late");
+        let outcome = hook(&format!("(sleep 2; touch '{}') & sleep 10", marker.display()))
+            .run(&event())
+            .await;
+        assert!(outcome.timed_out);
+
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        assert!(!marker.exists(), "background job outlived the timeout");
+    }
+}
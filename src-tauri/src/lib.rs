@@ -1,44 +1,249 @@
+pub mod alerts;
 pub mod api;
+pub mod cli;
 pub mod config;
+pub mod events;
+pub mod history;
+pub mod hooks;
+#[cfg(test)]
+mod mock_claude;
+pub mod mqtt;
+pub mod net;
+pub mod plan;
+pub mod poll;
+pub mod profile;
+pub mod release_notes;
+pub mod report;
+pub mod system;
+#[cfg(test)]
+mod test_support;
+pub mod theme;
 pub mod updater;
 pub mod usage;
-
-use api::ClaudeClient;
-use config::AppConfig;
+pub mod webhook;
+
+use alerts::BucketMarks;
+use api::{ApiEndpoints, ClaudeClient};
+use config::{AppConfig, BlinkSettings, UpdateSettings};
+use events::{Event, EventLog};
+use history::History;
+use hooks::HookSettings;
+use mqtt::{default_client_id, MqttPublisher, MqttSettings};
+use profile::UsageProfile;
+use report::{Report, ReportFormat, ReportPeriod};
+use net::NetworkConfig;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use tauri::{
     image::Image,
-    menu::{MenuBuilder, MenuItemBuilder},
+    menu::{CheckMenuItemBuilder, MenuBuilder, MenuItemBuilder, SubmenuBuilder},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
     AppHandle, Emitter, Manager, WebviewUrl, WebviewWindowBuilder,
 };
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use webhook::{Webhook, WebhookEvent, WebhookQueue};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+use tauri_plugin_notification::NotificationExt;
 use tauri_plugin_opener::OpenerExt;
 use tauri_plugin_store::StoreExt;
-use usage::{UsageColor, UsageState};
+use theme::{ColorTheme, Rgba};
+use usage::{BucketKind, ProjectionMode, ThresholdPolicy, UsageColor, UsageState};
 
 pub struct AppState {
     pub config: Mutex<AppConfig>,
-    pub client: Mutex<Option<ClaudeClient>>,
+    pub client: Mutex<Option<Arc<ClaudeClient>>>,
     pub usage: Mutex<Option<UsageState>>,
     pub blink_active: Arc<AtomicBool>,
+    /// Set when the current blink ran out of blinks (`max_blinks`).
+    /// Cleared once usage drops below blink level, so the next escalation blinks again.
+    pub blink_suppressed: Arc<AtomicBool>,
+    pub blink_wake: Arc<tokio::sync::Notify>,
     pub polling_active: Arc<AtomicBool>,
+    pub polling_paused: Arc<AtomicBool>,
+    pub alerts_snoozed_until: Mutex<Option<chrono::DateTime<chrono::Utc>>>,
+    /// Acknowledgments, history and events; acknowledgments are persisted in the state store.
+    pub poll: poll::PollContext,
+    /// Webhook deliveries waiting to be sent or retried.
+    pub webhook_queue: Mutex<WebhookQueue>,
+    /// Limits how many hook commands run at once; replaced when the limit changes.
+    pub hook_slots: Mutex<Arc<tokio::sync::Semaphore>>,
+    /// Connected MQTT publisher while MQTT is enabled.
+    pub mqtt: Mutex<Option<MqttPublisher>>,
+    /// The publisher's connection task. Held while (re)starting MQTT, so restarts run one
+    /// at a time.
+    pub mqtt_connection: tokio::sync::Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+    /// Last alert color we notified about per bucket, to avoid repeating notifications.
+    pub notified: Mutex<BucketMarks>,
+    /// Month ("2026-10") we last warned about extra-usage spend going over budget.
+    pub budget_notified: Mutex<Option<String>>,
     pub update_available: Mutex<Option<updater::UpdateInfo>>,
+    pub update_progress: Mutex<updater::UpdateProgress>,
 }
 
+const SNOOZE_DURATION_MINUTES: i64 = 60;
+const ACKNOWLEDGE_SHORTCUT: &str = "CommandOrControl+Alt+Shift+A";
+/// Stop blinking after this much keyboard/mouse inactivity (also covers a locked screen).
+const BLINK_IDLE_SUSPEND_SECS: f64 = 120.0;
+/// How often to re-check idle / reduced-motion while blinking is suspended.
+const BLINK_RECHECK_SECS: u64 = 10;
+/// How long a restart waits for the old MQTT connection to say goodbye.
+const MQTT_STOP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 #[tauri::command]
 fn get_usage(state: tauri::State<'_, AppState>) -> Option<UsageState> {
     state.usage.lock().unwrap().clone()
 }
 
+/// The hour-of-week profile learned from history so far, or None until there's enough.
+#[tauri::command]
+fn get_usage_profile(state: tauri::State<'_, AppState>) -> Option<UsageProfile> {
+    let org_id = state.config.lock().unwrap().org_id.clone();
+    UsageProfile::learn(state.poll.history.lock().unwrap().samples(&org_id))
+}
+
+/// The report for `period` as HTML, for the report window.
+#[tauri::command]
+fn get_report(state: tauri::State<'_, AppState>, period: ReportPeriod) -> String {
+    let org_id = state.config.lock().unwrap().org_id.clone();
+    let history = state.poll.history.lock().unwrap();
+    Report::generate(history.samples(&org_id), period, chrono::Utc::now()).html()
+}
+
+/// Save the report for `period` to the Downloads folder and return its path.
+#[tauri::command]
+fn export_report(
+    state: tauri::State<'_, AppState>,
+    period: ReportPeriod,
+    format: ReportFormat,
+) -> Result<String, String> {
+    let now = chrono::Utc::now();
+    let org_id = state.config.lock().unwrap().org_id.clone();
+    let report = {
+        let history = state.poll.history.lock().unwrap();
+        Report::generate(history.samples(&org_id), period, now)
+    };
+    let dir = dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .ok_or("No Downloads folder found")?;
+    let name = format!(
+        "tokentorch-{:?}-report-{}.{}",
+        period,
+        now.with_timezone(&chrono::Local).format("%Y-%m-%d"),
+        format.extension()
+    )
+    .to_lowercase();
+    let path = dir.join(name);
+    std::fs::write(&path, report.render(format))
+        .map_err(|e| format!("Failed to save report: {}", e))?;
+    Ok(path.display().to_string())
+}
+
+#[tauri::command]
+fn set_weekly_report(app: AppHandle, state: tauri::State<'_, AppState>, enabled: bool) {
+    let mut config = state.config.lock().unwrap();
+    config.weekly_report = enabled;
+    persist_config(&app, &config);
+}
+
+#[tauri::command]
+fn set_webhooks(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    mut webhooks: Vec<Webhook>,
+) -> Result<(), String> {
+    for hook in &webhooks {
+        hook.validate()?;
+    }
+    let mut config = state.config.lock().unwrap();
+    store_webhook_tokens(&mut webhooks, config.webhooks.len());
+    config.webhooks = webhooks;
+    persist_config(&app, &config);
+    Ok(())
+}
+
+#[tauri::command]
+fn set_hooks(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    hooks: HookSettings,
+) -> Result<(), String> {
+    hooks.validate()?;
+    let mut config = state.config.lock().unwrap();
+    if hooks.max_concurrent != config.hooks.max_concurrent {
+        *state.hook_slots.lock().unwrap() = Arc::new(tokio::sync::Semaphore::new(hooks.max_concurrent));
+    }
+    config.hooks = hooks;
+    persist_config(&app, &config);
+    Ok(())
+}
+
+#[tauri::command]
+fn set_mqtt(app: AppHandle, state: tauri::State<'_, AppState>, mut mqtt: MqttSettings) -> Result<(), String> {
+    if mqtt.client_id.trim().is_empty() {
+        mqtt.client_id = default_client_id();
+    }
+    mqtt.validate()?;
+    store_mqtt_password(&mut mqtt);
+    {
+        let mut config = state.config.lock().unwrap();
+        config.mqtt = mqtt;
+        persist_config(&app, &config);
+    }
+    tauri::async_runtime::spawn(async move {
+        start_mqtt(&app).await;
+    });
+    Ok(())
+}
+
+/// Run `hook` once with a test event built from the current usage and report how it went.
+#[tauri::command]
+async fn test_hook(state: tauri::State<'_, AppState>, hook: hooks::CommandHook) -> Result<String, String> {
+    hook.validate()?;
+    let event = test_event(&state)?;
+    let outcome = hook.run(&event).await;
+    if outcome.is_ok() {
+        Ok(outcome.summary())
+    } else {
+        let stderr = outcome.stderr.trim();
+        Err(if stderr.is_empty() {
+            outcome.summary()
+        } else {
+            format!("{}: {}", outcome.summary(), stderr)
+        })
+    }
+}
+
+/// Send a test message through `webhook` right away, without retries.
+#[tauri::command]
+async fn test_webhook(state: tauri::State<'_, AppState>, webhook: Webhook) -> Result<(), String> {
+    webhook.validate()?;
+    let event = test_event(&state)?;
+    let network = state.config.lock().unwrap().network.clone();
+    webhook.send(&webhook::client(&network), &event).await
+}
+
+/// A test event for the weekly bar (or the session bar without one).
+fn test_event(state: &AppState) -> Result<WebhookEvent, String> {
+    let profile = profile_label(&state.config.lock().unwrap());
+    let bar = state
+        .usage
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|s| s.weekly.clone().or_else(|| s.session.clone()))
+        .ok_or("No usage yet to put in a test event")?;
+    Ok(WebhookEvent::new(webhook::Trigger::Test, &bar, &profile))
+}
+
 #[tauri::command]
 fn save_config(
     app: AppHandle,
     _state: tauri::State<'_, AppState>,
     session_key: String,
     org_id: String,
+    profile_name: Option<String>,
 ) -> Result<String, String> {
-    apply_login_credentials(&app, session_key, org_id);
+    apply_login_credentials(&app, session_key, org_id, profile_name.unwrap_or_default());
     Ok("Configuration saved".to_string())
 }
 
@@ -47,6 +252,184 @@ fn get_config(state: tauri::State<'_, AppState>) -> AppConfig {
     state.config.lock().unwrap().clone()
 }
 
+#[tauri::command]
+fn set_theme(app: AppHandle, state: tauri::State<'_, AppState>, theme: ColorTheme) {
+    {
+        let mut config = state.config.lock().unwrap();
+        config.theme = theme;
+        persist_config(&app, &config);
+    }
+
+    if let Some(tray) = app.tray_by_id("main-tray") {
+        let usage_data = state.usage.lock().unwrap().clone();
+        update_tray_icon(&tray, usage_data.as_ref());
+    }
+    let _ = app.emit("theme-changed", theme);
+}
+
+#[tauri::command]
+fn set_thresholds(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    thresholds: ThresholdPolicy,
+) -> Result<(), String> {
+    thresholds.validate()?;
+    {
+        let mut config = state.config.lock().unwrap();
+        config.thresholds = thresholds;
+        persist_config(&app, &config);
+    }
+
+    // Recolor with the new thresholds right away
+    tauri::async_runtime::spawn(async move {
+        poll_usage(&app).await;
+    });
+    Ok(())
+}
+
+#[tauri::command]
+fn set_projection(app: AppHandle, state: tauri::State<'_, AppState>, projection: ProjectionMode) {
+    {
+        let mut config = state.config.lock().unwrap();
+        config.projection = projection;
+        persist_config(&app, &config);
+    }
+
+    // Reproject right away
+    tauri::async_runtime::spawn(async move {
+        poll_usage(&app).await;
+    });
+}
+
+#[tauri::command]
+fn set_extra_usage_budget(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    budget: Option<f64>,
+) -> Result<(), String> {
+    if budget.is_some_and(|b| !b.is_finite() || b <= 0.0) {
+        return Err("Budget must be a positive amount".to_string());
+    }
+    {
+        let mut config = state.config.lock().unwrap();
+        config.extra_usage_budget = budget;
+        persist_config(&app, &config);
+    }
+    *state.budget_notified.lock().unwrap() = None;
+
+    tauri::async_runtime::spawn(async move {
+        poll_usage(&app).await;
+    });
+    Ok(())
+}
+
+#[tauri::command]
+fn set_blink_settings(app: AppHandle, state: tauri::State<'_, AppState>, settings: BlinkSettings) {
+    {
+        let mut config = state.config.lock().unwrap();
+        config.blink = settings;
+        persist_config(&app, &config);
+    }
+
+    // Let the blink loop pick up the new mode immediately
+    state.blink_wake.notify_one();
+    let _ = app.emit("blink-settings-changed", settings);
+}
+
+#[tauri::command]
+fn set_update_settings(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    settings: UpdateSettings,
+) -> Result<(), String> {
+    settings.validate()?;
+    {
+        let mut config = state.config.lock().unwrap();
+        config.updates = settings;
+        persist_config(&app, &config);
+    }
+
+    // Re-check right away so a channel or feed switch shows (or drops) the offer
+    tauri::async_runtime::spawn(async move {
+        check_for_updates(&app).await;
+    });
+    Ok(())
+}
+
+#[tauri::command]
+fn set_network_config(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    network: NetworkConfig,
+) -> Result<(), String> {
+    network.validate()?;
+    {
+        let mut config = state.config.lock().unwrap();
+        config.network = network;
+        persist_config(&app, &config);
+        if state.client.lock().unwrap().is_some() {
+            replace_client(&state, &config);
+        }
+    }
+
+    // Poll right away through the new settings; update checks pick them up on their next run
+    tauri::async_runtime::spawn(async move {
+        poll_usage(&app).await;
+    });
+    Ok(())
+}
+
+#[tauri::command]
+fn set_api_endpoints(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    endpoints: ApiEndpoints,
+) -> Result<(), String> {
+    endpoints.validate()?;
+    {
+        let mut config = state.config.lock().unwrap();
+        config.api = endpoints;
+        persist_config(&app, &config);
+        if state.client.lock().unwrap().is_some() {
+            replace_client(&state, &config);
+        }
+    }
+
+    tauri::async_runtime::spawn(async move {
+        poll_usage(&app).await;
+    });
+    Ok(())
+}
+
+#[tauri::command]
+fn get_update_details(state: tauri::State<'_, AppState>) -> Option<updater::UpdateDetails> {
+    let progress = state.update_progress.lock().unwrap().clone();
+    state
+        .update_available
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|info| info.details(progress))
+}
+
+#[tauri::command]
+fn install_update(app: AppHandle) {
+    start_update_install(&app);
+}
+
+#[tauri::command]
+fn skip_update_version(app: AppHandle) {
+    skip_update(&app);
+    if let Some(window) = app.get_webview_window("update") {
+        let _ = window.close();
+    }
+}
+
+#[tauri::command]
+fn acknowledge_alert(app: AppHandle, kind: Option<BucketKind>) {
+    acknowledge_alerts(&app, kind);
+}
+
 #[tauri::command]
 fn refresh_now(app: AppHandle) {
     tauri::async_runtime::spawn(async move {
@@ -66,7 +449,7 @@ const KEYCHAIN_USER: &str = "session_key";
 // Previous keychain service name for migration
 const OLD_KEYCHAIN_SERVICE: &str = "com.claude-meter.app";
 
-fn save_session_key_to_keychain(session_key: &str) {
+pub(crate) fn save_session_key_to_keychain(session_key: &str) {
     match keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_USER) {
         Ok(entry) => {
             if let Err(e) = entry.set_password(session_key) {
@@ -79,7 +462,7 @@ fn save_session_key_to_keychain(session_key: &str) {
     }
 }
 
-fn load_session_key_from_keychain() -> Option<String> {
+pub(crate) fn load_session_key_from_keychain() -> Option<String> {
     match keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_USER) {
         Ok(entry) => match entry.get_password() {
             Ok(pw) => Some(pw),
@@ -95,6 +478,68 @@ fn load_session_key_from_keychain() -> Option<String> {
     }
 }
 
+/// Keep a credential other than the session key (webhook tokens, the MQTT password) in the
+/// keychain under `name`.
+pub(crate) fn save_secret(name: &str, secret: &str) {
+    match keyring::Entry::new(KEYCHAIN_SERVICE, name) {
+        Ok(entry) => {
+            if let Err(e) = entry.set_password(secret) {
+                eprintln!("[keychain] set_password for {} failed: {}", name, e);
+            }
+        }
+        Err(e) => eprintln!("[keychain] Entry::new failed: {}", e),
+    }
+}
+
+pub(crate) fn load_secret(name: &str) -> Option<String> {
+    match keyring::Entry::new(KEYCHAIN_SERVICE, name).and_then(|entry| entry.get_password()) {
+        Ok(secret) => Some(secret),
+        Err(e) => {
+            eprintln!("[keychain] get_password for {} failed: {}", name, e);
+            None
+        }
+    }
+}
+
+pub(crate) fn delete_secret(name: &str) {
+    match keyring::Entry::new(KEYCHAIN_SERVICE, name).and_then(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) => eprintln!("[keychain] delete_credential for {} failed: {}", name, e),
+    }
+}
+
+/// Save each webhook's token in the keychain, one entry per position, and point the hook
+/// at it. Entries of hooks without a token, or past the end of the list, are removed.
+fn store_webhook_tokens(webhooks: &mut [Webhook], previous: usize) {
+    let name = |i: usize| format!("webhook_token_{}", i);
+    for (i, hook) in webhooks.iter_mut().enumerate() {
+        hook.token_ref = store_secret(name(i), hook.token.as_deref().map(str::trim));
+    }
+    for i in webhooks.len()..previous {
+        delete_secret(&name(i));
+    }
+}
+
+/// Save the MQTT password in the keychain and point the settings at it.
+fn store_mqtt_password(mqtt: &mut MqttSettings) {
+    mqtt.password_ref = store_secret("mqtt_password".to_string(), mqtt.password.as_deref());
+}
+
+/// Save `secret` in the keychain under `name`, or remove the entry when it's empty, and
+/// return the reference the settings store keeps instead.
+fn store_secret(name: String, secret: Option<&str>) -> Option<String> {
+    match secret.filter(|s| !s.is_empty()) {
+        Some(secret) => {
+            save_secret(&name, secret);
+            Some(name)
+        }
+        None => {
+            delete_secret(&name);
+            None
+        }
+    }
+}
+
 fn persist_config(app: &AppHandle, config: &AppConfig) {
     // Session key goes to OS keychain
     save_session_key_to_keychain(&config.session_key);
@@ -106,9 +551,82 @@ fn persist_config(app: &AppHandle, config: &AppConfig) {
             "poll_interval_secs",
             serde_json::json!(config.poll_interval_secs),
         );
+        store.set("profiles", serde_json::json!(config.profiles));
+        store.set("theme", serde_json::json!(config.theme));
+        store.set("thresholds", serde_json::json!(config.thresholds));
+        store.set("projection", serde_json::json!(config.projection));
+        store.set("blink", serde_json::json!(config.blink));
+        store.set("updates", serde_json::json!(config.updates));
+        store.set("network", serde_json::json!(config.network));
+        store.set("api", serde_json::json!(config.api));
+        store.set("extra_usage_budget", serde_json::json!(config.extra_usage_budget));
+        store.set("weekly_report", serde_json::json!(config.weekly_report));
+        let webhooks: Vec<Webhook> = config.webhooks.iter().map(Webhook::without_token).collect();
+        store.set("webhooks", serde_json::json!(webhooks));
+        store.set("hooks", serde_json::json!(config.hooks));
+        store.set("mqtt", serde_json::json!(config.mqtt.without_password()));
+    }
+}
+
+fn persist_acknowledgments(app: &AppHandle, acks: &BucketMarks) {
+    if let Ok(store) = app.store("state.json") {
+        store.set("acknowledgments", serde_json::json!(acks));
+    }
+}
+
+fn load_acknowledgments(app: &AppHandle) -> BucketMarks {
+    app.store("state.json")
+        .ok()
+        .and_then(|store| store.get("acknowledgments"))
+        .and_then(|val| serde_json::from_value(val).ok())
+        .unwrap_or_default()
+}
+
+fn persist_cookies(app: &AppHandle, client: &ClaudeClient) {
+    if let Ok(store) = app.store("state.json") {
+        let cookies = serde_json::json!(client.export_cookies());
+        if store.get("cookies").as_ref() != Some(&cookies) {
+            store.set("cookies", cookies);
+        }
     }
 }
 
+fn load_cookies(app: &AppHandle) -> String {
+    app.store("state.json")
+        .ok()
+        .and_then(|store| store.get("cookies"))
+        .and_then(|val| val.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Point the shared client at `config`'s credentials, endpoints and network settings, keeping the
+/// cookie jar of the client it replaces. Idle connections of the old pool are dropped.
+fn replace_client(state: &AppState, config: &AppConfig) {
+    let mut client = state.client.lock().unwrap();
+    let cookies = client.as_ref().map(|c| c.cookies()).unwrap_or_default();
+    *client = Some(Arc::new(ClaudeClient::with_cookies(
+        &config.session_key,
+        &config.org_id,
+        &config.api,
+        &config.network,
+        cookies,
+    )));
+}
+
+/// Switch to the keychain's session key if it differs from ours, e.g. after the CLI saved a
+/// rotated key there. Returns the new client, or None if there was nothing to reload.
+fn reload_rotated_key(state: &AppState) -> Option<Arc<ClaudeClient>> {
+    let key = load_session_key_from_keychain().filter(|key| !key.is_empty())?;
+    let mut config = state.config.lock().unwrap();
+    if key == config.session_key {
+        return None;
+    }
+    eprintln!("[keychain] session key changed outside the app; reloading");
+    config.session_key = key;
+    replace_client(state, &config);
+    state.client.lock().unwrap().clone()
+}
+
 fn load_config(app: &AppHandle) -> AppConfig {
     let mut config = AppConfig::default();
 
@@ -119,16 +637,7 @@ fn load_config(app: &AppHandle) -> AppConfig {
 
     // Load non-secret config from store
     if let Ok(store) = app.store("config.json") {
-        if let Some(val) = store.get("org_id") {
-            if let Some(s) = val.as_str() {
-                config.org_id = s.to_string();
-            }
-        }
-        if let Some(val) = store.get("poll_interval_secs") {
-            if let Some(n) = val.as_u64() {
-                config.poll_interval_secs = n;
-            }
-        }
+        config.apply_stored(|key| store.get(key));
     }
 
     // Migrate: old keychain service name → new
@@ -159,36 +668,79 @@ fn load_config(app: &AppHandle) -> AppConfig {
         }
     }
 
+    // Migrate: webhook tokens saved in the store move to the keychain
+    if config.webhooks.iter().any(|hook| hook.token.is_some()) {
+        let count = config.webhooks.len();
+        store_webhook_tokens(&mut config.webhooks, count);
+        if let Ok(store) = app.store("config.json") {
+            let webhooks: Vec<Webhook> = config.webhooks.iter().map(Webhook::without_token).collect();
+            store.set("webhooks", serde_json::json!(webhooks));
+        }
+    }
+    for hook in &mut config.webhooks {
+        if let Some(name) = &hook.token_ref {
+            hook.token = load_secret(name);
+        }
+    }
+
+    // Migrate: same for the MQTT password
+    if config.mqtt.password.is_some() {
+        store_mqtt_password(&mut config.mqtt);
+        if let Ok(store) = app.store("config.json") {
+            store.set("mqtt", serde_json::json!(config.mqtt.without_password()));
+        }
+    }
+    if let Some(name) = &config.mqtt.password_ref {
+        config.mqtt.password = load_secret(name);
+    }
+
     config
 }
 
 async fn poll_usage(app: &AppHandle) {
     let state = app.state::<AppState>();
 
-    // Clone what we need from the client under the lock, then drop it before await
-    let fetch_params = {
-        let client_guard = state.client.lock().unwrap();
-        match client_guard.as_ref() {
-            Some(client) => Some((client.session_key().to_string(), client.org_id().to_string())),
-            None => None,
-        }
-    };
-
-    let Some((session_key, org_id)) = fetch_params else {
+    // Share the client (and its connection pool) without holding the lock across await
+    let Some(mut client) = state.client.lock().unwrap().clone() else {
         return;
     };
+    let (thresholds, projection, extra_budget) = {
+        let config = state.config.lock().unwrap();
+        (config.thresholds, config.projection, config.extra_usage_budget)
+    };
 
-    let client = ClaudeClient::new(&session_key, &org_id);
+    let mut outcome = poll::poll_once(&client, &thresholds, projection, extra_budget, &state.poll).await;
+    // `tokentorch status` may have rotated the key under us; retry with the keychain's copy
+    if !outcome.is_ok() {
+        if let Some(reloaded) = reload_rotated_key(&state) {
+            client = reloaded;
+            outcome = poll::poll_once(&client, &thresholds, projection, extra_budget, &state.poll).await;
+        }
+    }
+    let poll::PollOutcome {
+        state: usage_state,
+        refreshed_session_key,
+        acknowledgments_changed,
+        events,
+    } = outcome;
+    if acknowledgments_changed {
+        persist_acknowledgments(app, &state.poll.acknowledgments.lock().unwrap());
+    }
 
-    match client.fetch_usage().await {
-        Ok(result) => {
-            let usage_state = usage::compute_state(&result.usage);
-            let worst = usage::worst_color(&usage_state);
+    match usage_state.error {
+        None => {
+            notify_escalations(app, &usage_state);
+            notify_over_budget(app, &usage_state);
+            notify_weekly_report(app);
 
             // Set/clear blink flag
-            state.blink_active.store(worst == UsageColor::RedBlink, Ordering::Relaxed);
+            update_blink(app, &usage_state);
 
-            *state.usage.lock().unwrap() = Some(usage_state.clone());
+            let previous = state.usage.lock().unwrap().replace(usage_state.clone());
+            dispatch_transitions(app, previous.as_ref(), &usage_state, &events);
+            if let Some(publisher) = state.mqtt.lock().unwrap().as_mut() {
+                publisher.publish(&usage_state);
+            }
 
             // Update tray icon
             if let Some(tray) = app.tray_by_id("main-tray") {
@@ -199,37 +751,344 @@ async fn poll_usage(app: &AppHandle) {
             let _ = app.emit("usage-updated", &usage_state);
 
             // Handle refreshed session key
-            if let Some(new_key) = result.refreshed_session_key {
+            if let Some(new_key) = refreshed_session_key {
                 let mut config = state.config.lock().unwrap();
-                config.session_key = new_key.clone();
+                config.session_key = new_key;
                 persist_config(app, &config);
-
-                if let Some(c) = state.client.lock().unwrap().as_mut() {
-                    c.update_session_key(new_key);
-                }
             }
+            persist_cookies(app, &client);
         }
-        Err(err) => {
-            state.blink_active.store(false, Ordering::Relaxed);
+        Some(_) => {
+            set_blinking(&state, false);
 
-            let error_state = UsageState {
-                session: None,
-                weekly: None,
-                last_updated: chrono::Utc::now().to_rfc3339(),
-                error: Some(err.clone()),
-            };
-            *state.usage.lock().unwrap() = Some(error_state.clone());
+            *state.usage.lock().unwrap() = Some(usage_state.clone());
 
             if let Some(tray) = app.tray_by_id("main-tray") {
                 update_tray_icon(&tray, None);
             }
 
-            let _ = app.emit("usage-updated", &error_state);
+            let _ = app.emit("usage-updated", &usage_state);
         }
     }
+
+    refresh_tray_menu(app);
+}
+
+/// Start or stop the tray blink for unacknowledged RedBlink bars, honoring snooze.
+fn update_blink(app: &AppHandle, usage_state: &UsageState) {
+    let state = app.state::<AppState>();
+    let any_blink = usage_state
+        .bars()
+        .any(|bar| bar.color == UsageColor::RedBlink);
+    if !any_blink {
+        // Dropping below blink level re-arms blinking for the next escalation
+        state.blink_suppressed.store(false, Ordering::Relaxed);
+    }
+    let blink = usage_state
+        .bars()
+        .any(|bar| bar.color == UsageColor::RedBlink && !bar.acknowledged)
+        && !alerts_snoozed(app)
+        && !state.blink_suppressed.load(Ordering::Relaxed);
+    set_blinking(&state, blink);
+}
+
+fn set_blinking(state: &AppState, on: bool) {
+    let was_on = state.blink_active.swap(on, Ordering::Relaxed);
+    if on && !was_on {
+        state.blink_wake.notify_one();
+    }
+}
+
+/// A tray click while blinking counts as "seen it" when stop-on-click is enabled.
+fn acknowledge_blink_click(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    if !state.blink_active.load(Ordering::Relaxed) {
+        return;
+    }
+    if !state.config.lock().unwrap().blink.stop_on_click {
+        return;
+    }
+    acknowledge_alerts(app, None);
+}
+
+/// Acknowledge the current alert of one bucket (or all alerting buckets when `kind` is None):
+/// stops blinking and notifications for it until its color changes or its window resets.
+fn acknowledge_alerts(app: &AppHandle, kind: Option<BucketKind>) {
+    let state = app.state::<AppState>();
+    let usage_state = {
+        let mut usage = state.usage.lock().unwrap();
+        let Some(usage_state) = usage.as_mut() else {
+            return;
+        };
+        let mut acks = state.poll.acknowledgments.lock().unwrap();
+        for bar in usage_state.bars() {
+            if alerts::is_alert(bar.color) && kind.is_none_or(|k| k == bar.kind) {
+                acks.mark(bar);
+            }
+        }
+        acks.apply(usage_state);
+        persist_acknowledgments(app, &acks);
+        usage_state.clone()
+    };
+
+    update_blink(app, &usage_state);
+    restore_tray_icon(app);
+    refresh_tray_menu(app);
+    let _ = app.emit("usage-updated", &usage_state);
+}
+
+/// Desktop notification when a bucket escalates into red, unless acknowledged or snoozed.
+fn notify_escalations(app: &AppHandle, usage_state: &UsageState) {
+    let snoozed = alerts_snoozed(app);
+    let state = app.state::<AppState>();
+    let mut notified = state.notified.lock().unwrap();
+    for bar in usage_state.bars() {
+        if !alerts::is_alert(bar.color) {
+            notified.forget(bar.kind);
+            continue;
+        }
+        let escalated = notified.is_escalation(bar);
+        notified.mark(bar);
+        if !escalated || bar.acknowledged || snoozed {
+            continue;
+        }
+
+        let title = if bar.color == UsageColor::RedBlink {
+            format!("{} limit imminent", bar.label)
+        } else {
+            format!("{} usage trending over the limit", bar.label)
+        };
+        let mut body = format!("Projected {:.0}% \u{00B7} {}", bar.projected, bar.reset_display);
+        if let Some(gap) = &bar.gap_display {
+            body.push_str(&format!(" \u{00B7} {}", gap));
+        }
+        if let Err(e) = app.notification().builder().title(title).body(body).show() {
+            eprintln!("[notification] show failed: {}", e);
+        }
+    }
+}
+
+/// Warn once a month when extra-usage spend is projected past the user's budget.
+fn notify_over_budget(app: &AppHandle, usage_state: &UsageState) {
+    let Some(extra) = usage_state.extra.as_ref().filter(|e| e.over_budget) else {
+        return;
+    };
+    let month = chrono::Utc::now().format("%Y-%m").to_string();
+    let state = app.state::<AppState>();
+    {
+        let mut notified = state.budget_notified.lock().unwrap();
+        if notified.as_deref() == Some(month.as_str()) {
+            return;
+        }
+        *notified = Some(month);
+    }
+    if alerts_snoozed(app) {
+        return;
+    }
+
+    let body = format!("Extra usage: {}", extra.display);
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title("Extra usage projected over budget")
+        .body(body)
+        .show()
+    {
+        eprintln!("[notification] show failed: {}", e);
+    }
+}
+
+/// Summarize the past week once per ISO week, if the user asked for it. Not an alert, so
+/// snoozing doesn't hold it back.
+fn notify_weekly_report(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let org_id = {
+        let config = state.config.lock().unwrap();
+        if !config.weekly_report {
+            return;
+        }
+        config.org_id.clone()
+    };
+    let Ok(store) = app.store("state.json") else {
+        return;
+    };
+    let now = chrono::Utc::now();
+    let week = now.with_timezone(&chrono::Local).format("%G-W%V").to_string();
+    if store.get("report_notified").as_ref().and_then(|v| v.as_str()) == Some(week.as_str()) {
+        return;
+    }
+    store.set("report_notified", serde_json::json!(week));
+
+    let summary = {
+        let history = state.poll.history.lock().unwrap();
+        Report::generate(history.samples(&org_id), ReportPeriod::Week, now).summary_line()
+    };
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title("Your week in Claude usage")
+        .body(format!("{}. Open Usage Report for details.", summary))
+        .show()
+    {
+        eprintln!("[notification] show failed: {}", e);
+    }
+}
+
+/// Hand color changes and resets to the configured webhooks and hook commands.
+fn dispatch_transitions(
+    app: &AppHandle,
+    previous: Option<&UsageState>,
+    usage_state: &UsageState,
+    events: &[Event],
+) {
+    let state = app.state::<AppState>();
+    let (webhooks, commands, profile) = {
+        let config = state.config.lock().unwrap();
+        (config.webhooks.clone(), config.hooks.hooks.clone(), profile_label(&config))
+    };
+    if webhooks.is_empty() && commands.is_empty() {
+        return;
+    }
+    let triggered = webhook::transitions(previous, usage_state, events, &profile);
+    if triggered.is_empty() {
+        return;
+    }
+
+    if !webhooks.is_empty() {
+        state
+            .webhook_queue
+            .lock()
+            .unwrap()
+            .enqueue(&webhooks, &triggered, std::time::Instant::now());
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            flush_webhooks(&app).await;
+        });
+    }
+
+    if !commands.is_empty() {
+        let slots = state.hook_slots.lock().unwrap().clone();
+        tauri::async_runtime::spawn(async move {
+            hooks::run_all(&commands, &triggered, slots).await;
+        });
+    }
+}
+
+/// (Re)connect to the configured MQTT broker, or disconnect if MQTT is off.
+async fn start_mqtt(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let mut connection = state.mqtt_connection.lock().await;
+    // Dropping the old publisher marks it offline and disconnects. Let that go out before
+    // connecting again, or the broker could get its "offline" after the new "online".
+    state.mqtt.lock().unwrap().take();
+    if let Some(mut old) = connection.take() {
+        if tokio::time::timeout(MQTT_STOP_TIMEOUT, &mut old).await.is_err() {
+            eprintln!("[mqtt] old connection didn't close in time");
+            old.abort();
+        }
+    }
+
+    let settings = state.config.lock().unwrap().mqtt.clone();
+    if !settings.enabled {
+        return;
+    }
+    let (mut publisher, run) = MqttPublisher::start(settings);
+    *connection = Some(tauri::async_runtime::spawn(run));
+    // Publish what we have now instead of waiting for the next poll
+    if let Some(usage) = state.usage.lock().unwrap().as_ref().filter(|u| u.error.is_none()) {
+        publisher.publish(usage);
+    }
+    *state.mqtt.lock().unwrap() = Some(publisher);
+}
+
+async fn flush_webhooks(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    if state.webhook_queue.lock().unwrap().is_empty() {
+        return;
+    }
+    let client = webhook::client(&state.config.lock().unwrap().network);
+    webhook::flush(&state.webhook_queue, &client, std::time::Instant::now()).await;
+}
+
+/// Name the usage belongs to in team messages: the active profile, or the org ID.
+fn profile_label(config: &AppConfig) -> String {
+    config
+        .active_profile()
+        .map(|p| p.name.clone())
+        .unwrap_or_else(|| config.org_id.clone())
+}
+
+fn alerts_snoozed(app: &AppHandle) -> bool {
+    let state = app.state::<AppState>();
+    let mut snoozed_until = state.alerts_snoozed_until.lock().unwrap();
+    match *snoozed_until {
+        Some(until) if until > chrono::Utc::now() => true,
+        Some(_) => {
+            *snoozed_until = None;
+            false
+        }
+        None => false,
+    }
+}
+
+fn toggle_alert_snooze(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let snoozed = alerts_snoozed(app);
+    *state.alerts_snoozed_until.lock().unwrap() = if snoozed {
+        None
+    } else {
+        Some(chrono::Utc::now() + chrono::Duration::minutes(SNOOZE_DURATION_MINUTES))
+    };
+
+    // Apply immediately rather than waiting for the next poll
+    let usage_data = state.usage.lock().unwrap().clone();
+    if let Some(usage_state) = &usage_data {
+        update_blink(app, usage_state);
+    }
+    restore_tray_icon(app);
+    refresh_tray_menu(app);
+}
+
+fn copy_status_to_clipboard(app: &AppHandle) {
+    let text = match app.state::<AppState>().usage.lock().unwrap().as_ref() {
+        Some(s) => usage::status_text(s),
+        None => "No usage data".to_string(),
+    };
+    if let Err(e) = app.clipboard().write_text(text) {
+        eprintln!("[clipboard] write_text failed: {}", e);
+    }
+}
+
+fn switch_profile(app: &AppHandle, index: usize) {
+    let state = app.state::<AppState>();
+    {
+        let mut config = state.config.lock().unwrap();
+        let Some(profile) = config.profiles.get(index).cloned() else {
+            return;
+        };
+        if profile.org_id == config.org_id {
+            return;
+        }
+        config.org_id = profile.org_id;
+        persist_config(app, &config);
+        replace_client(&state, &config);
+    }
+
+    *state.usage.lock().unwrap() = None;
+    set_blinking(&state, false);
+    if let Some(tray) = app.tray_by_id("main-tray") {
+        update_tray_icon(&tray, None);
+    }
+    refresh_tray_menu(app);
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        poll_usage(&app).await;
+    });
 }
 
 fn update_tray_icon(tray: &tauri::tray::TrayIcon, state: Option<&UsageState>) {
+    let theme = tray.app_handle().state::<AppState>().config.lock().unwrap().theme;
     let (s_pct, s_color, w_pct, w_color) = match state {
         Some(s) => (
             s.session.as_ref().map(|b| b.utilization / 100.0).unwrap_or(0.0),
@@ -239,17 +1098,23 @@ fn update_tray_icon(tray: &tauri::tray::TrayIcon, state: Option<&UsageState>) {
         ),
         None => (0.0, UsageColor::Gray, 0.0, UsageColor::Gray),
     };
-    let (rgba, w, h) = generate_bars_rgba(s_pct, s_color, w_pct, w_color);
+    let (rgba, w, h) = generate_bars_rgba(theme, s_pct, s_color, w_pct, w_color);
     let icon = Image::new_owned(rgba, w, h);
     let _ = tray.set_icon(Some(icon));
+    let _ = tray.set_icon_as_template(theme.is_template());
+
+    let tooltip = match state {
+        Some(s) => format!("TokenTorch\n{}", usage::status_text(s)),
+        None => "TokenTorch".to_string(),
+    };
+    let _ = tray.set_tooltip(Some(tooltip));
 }
 
-fn color_rgb(color: UsageColor) -> (u8, u8, u8) {
-    match color {
-        UsageColor::Green => (76, 175, 80),
-        UsageColor::Yellow => (255, 152, 0),
-        UsageColor::Red | UsageColor::RedBlink => (198, 40, 40),
-        UsageColor::Gray => (120, 120, 120),
+/// Redraw the tray icon from the latest usage (e.g. to undo a dimmed blink frame).
+fn restore_tray_icon(app: &AppHandle) {
+    if let Some(tray) = app.tray_by_id("main-tray") {
+        let usage_data = app.state::<AppState>().usage.lock().unwrap().clone();
+        update_tray_icon(&tray, usage_data.as_ref());
     }
 }
 
@@ -294,8 +1159,8 @@ fn draw_rounded_bar(
     img_width: u32,
     x: u32, y: u32, w: u32, h: u32,
     radius: f64,
-    track: (u8, u8, u8),
-    fill: (u8, u8, u8),
+    track: Rgba,
+    fill: Rgba,
     fill_pct: f64,
 ) {
     let fill_w = ((w as f64) * fill_pct.clamp(0.0, 1.0)) as u32;
@@ -305,16 +1170,17 @@ fn draw_rounded_bar(
                 continue;
             }
             let idx = ((py * img_width + px) * 4) as usize;
-            let (r, g, b) = if px < x + fill_w { fill } else { track };
+            let (r, g, b, a) = if px < x + fill_w { fill } else { track };
             rgba[idx] = r;
             rgba[idx + 1] = g;
             rgba[idx + 2] = b;
-            rgba[idx + 3] = 255;
+            rgba[idx + 3] = a;
         }
     }
 }
 
 fn generate_bars_rgba(
+    theme: ColorTheme,
     session_pct: f64,
     session_color: UsageColor,
     weekly_pct: f64,
@@ -328,19 +1194,19 @@ fn generate_bars_rgba(
         (32u32, 32u32, 2u32, 28u32, 10u32, 4.0f64, 4u32, 4u32)
     };
     let bottom_y = top_y + bar_h + gap;
-    let track = (68u8, 68, 72);
+    let track = theme.track();
 
     let mut rgba = vec![0u8; (width * height * 4) as usize];
 
     draw_rounded_bar(
         &mut rgba, width,
         bar_x, top_y, bar_w, bar_h, radius,
-        track, color_rgb(session_color), session_pct,
+        track, theme.fill(session_color), session_pct,
     );
     draw_rounded_bar(
         &mut rgba, width,
         bar_x, bottom_y, bar_w, bar_h, radius,
-        track, color_rgb(weekly_color), weekly_pct,
+        track, theme.fill(weekly_color), weekly_pct,
     );
 
     (rgba, width, height)
@@ -359,78 +1225,267 @@ fn start_polling_loop(app: &AppHandle) {
         let interval = state.config.lock().unwrap().poll_interval_secs;
         loop {
             tokio::time::sleep(tokio::time::Duration::from_secs(interval)).await;
+            if state.polling_paused.load(Ordering::Relaxed) {
+                continue;
+            }
             poll_usage(&app_handle).await;
         }
     });
 }
 
-fn apply_login_credentials(app: &AppHandle, session_key: String, org_id: String) {
+fn apply_login_credentials(
+    app: &AppHandle,
+    session_key: String,
+    org_id: String,
+    profile_name: String,
+) {
     let state = app.state::<AppState>();
     {
         let mut config = state.config.lock().unwrap();
-        config.session_key = session_key.clone();
+        config.session_key = session_key;
         config.org_id = org_id.clone();
+        config.upsert_profile(&profile_name, &org_id);
         persist_config(app, &config);
+        replace_client(&state, &config);
     }
 
-    let client = ClaudeClient::new(&session_key, &org_id);
-    *state.client.lock().unwrap() = Some(client);
-
     // Close setup window
     if let Some(w) = app.get_webview_window("setup") {
         let _ = w.close();
     }
 
+    refresh_tray_menu(app);
     start_polling_loop(app);
 }
 
-fn build_tray_menu(
-    app: &AppHandle,
-    update: Option<&updater::UpdateInfo>,
-) -> tauri::Result<tauri::menu::Menu<tauri::Wry>> {
+/// Rebuild the tray menu from current state (usage, update, pause/snooze, profiles).
+fn build_tray_menu(app: &AppHandle) -> tauri::Result<tauri::menu::Menu<tauri::Wry>> {
+    let state = app.state::<AppState>();
+    let update = state.update_available.lock().unwrap().clone();
+    let update_progress = state.update_progress.lock().unwrap().clone();
+    let usage_data = state.usage.lock().unwrap().clone();
+    let (profiles, active_org) = {
+        let config = state.config.lock().unwrap();
+        (config.profiles.clone(), config.org_id.clone())
+    };
+    let paused = state.polling_paused.load(Ordering::Relaxed);
+    let unacknowledged = usage_data.as_ref().is_some_and(|s| {
+        s.bars()
+            .any(|bar| alerts::is_alert(bar.color) && !bar.acknowledged)
+    });
+    let snoozed_until = if alerts_snoozed(app) {
+        *state.alerts_snoozed_until.lock().unwrap()
+    } else {
+        None
+    };
+
     let mut builder = MenuBuilder::new(app);
 
     if let Some(info) = update {
-        let label = format!("\u{2B06} Update v{} available", info.version);
-        let update_item = MenuItemBuilder::with_id("update", label).build(app)?;
-        builder = builder.item(&update_item).separator();
+        let label = match update_progress {
+            updater::UpdateProgress::Idle => format!("\u{2B06} Update v{} available\u{2026}", info.version),
+            updater::UpdateProgress::Downloading => format!("Downloading Update v{}\u{2026}", info.version),
+            updater::UpdateProgress::ReadyToRestart => format!("\u{2B06} Restart to Update to v{}", info.version),
+        };
+        let update_item = MenuItemBuilder::with_id("update", label)
+            .enabled(update_progress != updater::UpdateProgress::Downloading)
+            .build(app)?;
+        builder = builder.item(&update_item);
+        if update_progress == updater::UpdateProgress::Idle {
+            let skip = MenuItemBuilder::with_id("skip_update", "Skip This Version").build(app)?;
+            builder = builder.item(&skip);
+        }
+        builder = builder.separator();
     }
 
+    // Disabled info rows, one per bucket
+    let mut info_lines: Vec<String> = Vec::new();
+    match &usage_data {
+        Some(s) => {
+            info_lines.extend(
+                [&s.session, &s.weekly]
+                    .iter()
+                    .filter_map(|b| b.as_ref())
+                    .map(usage::bar_summary),
+            );
+            if let Some(err) = &s.error {
+                info_lines.push(format!("Error: {}", err));
+            }
+        }
+        None => info_lines.push("No usage data yet".to_string()),
+    }
+    for (i, line) in info_lines.iter().enumerate() {
+        let item = MenuItemBuilder::with_id(format!("info:{}", i), line)
+            .enabled(false)
+            .build(app)?;
+        builder = builder.item(&item);
+    }
+    builder = builder.separator();
+
     let refresh = MenuItemBuilder::with_id("refresh", "Refresh Now").build(app)?;
+    let pause = CheckMenuItemBuilder::with_id("pause_polling", "Pause Polling")
+        .checked(paused)
+        .build(app)?;
+    let snooze_label = match snoozed_until {
+        Some(until) => format!(
+            "Alerts Snoozed until {} (Resume)",
+            until.with_timezone(&chrono::Local).format("%H:%M")
+        ),
+        None => "Snooze Alerts for 1h".to_string(),
+    };
+    let snooze = MenuItemBuilder::with_id("snooze_alerts", snooze_label).build(app)?;
+    let acknowledge = MenuItemBuilder::with_id("acknowledge", "Acknowledge Alerts")
+        .enabled(unacknowledged)
+        .build(app)?;
+    let copy_status =
+        MenuItemBuilder::with_id("copy_status", "Copy Status to Clipboard").build(app)?;
+    builder = builder
+        .item(&refresh)
+        .item(&pause)
+        .item(&acknowledge)
+        .item(&snooze);
+
+    if !profiles.is_empty() {
+        let mut profile_menu = SubmenuBuilder::new(app, "Profile");
+        for (i, profile) in profiles.iter().enumerate() {
+            let item = CheckMenuItemBuilder::with_id(format!("profile:{}", i), &profile.name)
+                .checked(profile.org_id == active_org)
+                .build(app)?;
+            profile_menu = profile_menu.item(&item);
+        }
+        builder = builder.item(&profile_menu.build()?);
+    }
+
     let open_claude =
         MenuItemBuilder::with_id("open_claude", "Open claude.ai Usage").build(app)?;
+    let report = MenuItemBuilder::with_id("report", "Usage Report...").build(app)?;
     let settings = MenuItemBuilder::with_id("settings", "Settings...").build(app)?;
     let quit = MenuItemBuilder::with_id("quit", "Quit TokenTorch").build(app)?;
 
     builder
-        .item(&refresh)
+        .item(&copy_status)
         .separator()
         .item(&open_claude)
+        .item(&report)
         .item(&settings)
         .separator()
         .item(&quit)
         .build()
 }
 
+fn refresh_tray_menu(app: &AppHandle) {
+    if let Some(tray) = app.tray_by_id("main-tray") {
+        if let Ok(menu) = build_tray_menu(app) {
+            let _ = tray.set_menu(Some(menu));
+        }
+    }
+}
+
+/// Tray "update" action: restart into a staged update, otherwise show what's new.
+fn handle_update_click(app: &AppHandle) {
+    let progress = app.state::<AppState>().update_progress.lock().unwrap().clone();
+    match progress {
+        updater::UpdateProgress::ReadyToRestart => app.restart(),
+        _ => show_update_window(app),
+    }
+}
+
+fn set_update_progress(app: &AppHandle, progress: updater::UpdateProgress) {
+    *app.state::<AppState>().update_progress.lock().unwrap() = progress.clone();
+    refresh_tray_menu(app);
+    let _ = app.emit("update-progress", progress);
+}
+
+/// Install in-app when the release is signed for this platform, otherwise open the release page.
+fn start_update_install(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let Some(info) = state.update_available.lock().unwrap().clone() else {
+        return;
+    };
+    let progress = state.update_progress.lock().unwrap().clone();
+    match progress {
+        updater::UpdateProgress::ReadyToRestart => app.restart(),
+        updater::UpdateProgress::Downloading => {}
+        updater::UpdateProgress::Idle if info.can_self_update() => {
+            set_update_progress(app, updater::UpdateProgress::Downloading);
+
+            let network = state.config.lock().unwrap().network.clone();
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                let progress = match updater::stage_update(&info, &network).await {
+                    Ok(_) => updater::UpdateProgress::ReadyToRestart,
+                    Err(e) => {
+                        eprintln!("[updater] {}", e);
+                        let _ = app
+                            .notification()
+                            .builder()
+                            .title("Update failed")
+                            .body(format!("v{} could not be installed: {}", info.version, e))
+                            .show();
+                        updater::UpdateProgress::Idle
+                    }
+                };
+                set_update_progress(&app, progress);
+            });
+        }
+        updater::UpdateProgress::Idle => {
+            let _ = app.opener().open_url(&info.url, None::<&str>);
+        }
+    }
+}
+
+/// Stop offering this release until a newer one ships (tray menu or update window).
+fn skip_update(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    if *state.update_progress.lock().unwrap() != updater::UpdateProgress::Idle {
+        return;
+    }
+    let Some(info) = state.update_available.lock().unwrap().take() else {
+        return;
+    };
+    {
+        let mut config = state.config.lock().unwrap();
+        config.updates.skipped_version = Some(info.version);
+        persist_config(app, &config);
+    }
+    refresh_tray_menu(app);
+}
+
+/// Check the configured feed; with update checks disabled this only clears a stale offer.
+async fn check_for_updates(app: &AppHandle) {
+    let (settings, network) = {
+        let state = app.state::<AppState>();
+        let config = state.config.lock().unwrap();
+        (config.updates.clone(), config.network.clone())
+    };
+    let info = updater::check_for_update(&settings, &network).await;
+
+    let state = app.state::<AppState>();
+    // Don't swap the release out from under a download or staged install
+    if *state.update_progress.lock().unwrap() != updater::UpdateProgress::Idle {
+        return;
+    }
+    *state.update_available.lock().unwrap() = info;
+    refresh_tray_menu(app);
+}
+
+/// Retry failed webhook deliveries as they come due.
+fn start_webhook_retry_loop(app: &AppHandle) {
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(15)).await;
+            flush_webhooks(&app_handle).await;
+        }
+    });
+}
+
 fn start_update_check_loop(app: &AppHandle) {
     let app_handle = app.clone();
     tauri::async_runtime::spawn(async move {
         loop {
-            if let Some(info) = updater::check_for_update().await {
-                let state = app_handle.state::<AppState>();
-                let update_clone = {
-                    let mut update = state.update_available.lock().unwrap();
-                    *update = Some(info);
-                    update.clone()
-                };
-                if let Some(tray) = app_handle.tray_by_id("main-tray") {
-                    if let Ok(menu) =
-                        build_tray_menu(&app_handle, update_clone.as_ref())
-                    {
-                        let _ = tray.set_menu(Some(menu));
-                    }
-                }
-            }
+            // No requests go out while update checks are disabled
+            check_for_updates(&app_handle).await;
             // Re-check every 6 hours
             tokio::time::sleep(tokio::time::Duration::from_secs(6 * 60 * 60)).await;
         }
@@ -438,7 +1493,7 @@ fn start_update_check_loop(app: &AppHandle) {
 }
 
 const POPUP_WIDTH: f64 = 360.0;
-const POPUP_HEIGHT: f64 = 120.0;
+const POPUP_HEIGHT: f64 = 192.0;
 
 fn popup_position(pos: &tauri::PhysicalPosition<f64>) -> tauri::PhysicalPosition<i32> {
     let x = (pos.x as i32).saturating_sub((POPUP_WIDTH / 2.0) as i32);
@@ -501,6 +1556,38 @@ fn show_popup(app: &AppHandle, position: Option<tauri::PhysicalPosition<f64>>) {
     }
 }
 
+fn show_update_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("update") {
+        let _ = window.show();
+        let _ = window.set_focus();
+        return;
+    }
+
+    let _ = WebviewWindowBuilder::new(app, "update", WebviewUrl::App("update.html".into()))
+        .title("TokenTorch Update")
+        .inner_size(520.0, 560.0)
+        .center()
+        .visible(true)
+        .focused(true)
+        .build();
+}
+
+fn show_report_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("report") {
+        let _ = window.show();
+        let _ = window.set_focus();
+        return;
+    }
+
+    let _ = WebviewWindowBuilder::new(app, "report", WebviewUrl::App("report.html".into()))
+        .title("TokenTorch Usage Report")
+        .inner_size(620.0, 520.0)
+        .center()
+        .visible(true)
+        .focused(true)
+        .build();
+}
+
 fn show_setup(app: &AppHandle) {
     if let Some(window) = app.get_webview_window("setup") {
         let _ = window.show();
@@ -510,7 +1597,7 @@ fn show_setup(app: &AppHandle) {
 
     let _ = WebviewWindowBuilder::new(app, "setup", WebviewUrl::App("setup.html".into()))
         .title("TokenTorch Setup")
-        .inner_size(480.0, 400.0)
+        .inner_size(480.0, 620.0)
         .resizable(false)
         .center()
         .visible(true)
@@ -520,22 +1607,34 @@ fn show_setup(app: &AppHandle) {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Install a staged update or roll back a broken one before anything else starts
+    updater::apply_staged_update();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_single_instance::init(|_app, _args, _cwd| {}))
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_store::Builder::default().build())
         .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_clipboard_manager::init())
         .setup(|app| {
             // Load persisted config
             let config = load_config(&app.handle());
             let client = if config.is_configured() {
-                Some(ClaudeClient::new(&config.session_key, &config.org_id))
+                let client = ClaudeClient::new(
+                    &config.session_key,
+                    &config.org_id,
+                    &config.api,
+                    &config.network,
+                );
+                client.import_cookies(&load_cookies(app.handle()));
+                Some(Arc::new(client))
             } else {
                 None
             };
 
             let blink_active = Arc::new(AtomicBool::new(false));
+            let blink_wake = Arc::new(tokio::sync::Notify::new());
 
             let polling_active = Arc::new(AtomicBool::new(false));
 
@@ -544,34 +1643,55 @@ pub fn run() {
                 client: Mutex::new(client),
                 usage: Mutex::new(None),
                 blink_active: blink_active.clone(),
+                blink_suppressed: Arc::new(AtomicBool::new(false)),
+                blink_wake: blink_wake.clone(),
                 polling_active: polling_active.clone(),
+                polling_paused: Arc::new(AtomicBool::new(false)),
+                alerts_snoozed_until: Mutex::new(None),
+                poll: poll::PollContext {
+                    acknowledgments: Mutex::new(load_acknowledgments(app.handle())),
+                    history: Mutex::new(
+                        History::default_path()
+                            .map(|path| History::open(path, &config.org_id))
+                            .unwrap_or_default(),
+                    ),
+                    events: Mutex::new(
+                        EventLog::default_path()
+                            .map(|path| EventLog::open(path, &config.org_id))
+                            .unwrap_or_default(),
+                    ),
+                },
+                webhook_queue: Mutex::new(WebhookQueue::default()),
+                hook_slots: Mutex::new(Arc::new(tokio::sync::Semaphore::new(config.hooks.max_concurrent))),
+                mqtt: Mutex::new(None),
+                mqtt_connection: tokio::sync::Mutex::new(None),
+                notified: Mutex::new(BucketMarks::default()),
+                budget_notified: Mutex::new(None),
                 update_available: Mutex::new(None),
+                update_progress: Mutex::new(updater::UpdateProgress::Idle),
             });
 
-            // Build tray menu (no update info yet)
-            let menu = build_tray_menu(app.handle(), None)?;
+            // Build tray menu (no usage or update info yet)
+            let menu = build_tray_menu(app.handle())?;
 
             // Create initial icon — empty gray bars
             let (rgba, icon_w, icon_h) = generate_bars_rgba(
-                0.0, UsageColor::Gray, 0.0, UsageColor::Gray,
+                config.theme, 0.0, UsageColor::Gray, 0.0, UsageColor::Gray,
             );
             let icon = Image::new_owned(rgba, icon_w, icon_h);
 
             let _tray = TrayIconBuilder::with_id("main-tray")
                 .icon(icon)
-                .icon_as_template(false)
+                .icon_as_template(config.theme.is_template())
                 .tooltip("TokenTorch")
                 .show_menu_on_left_click(false)
                 .menu(&menu)
                 .on_menu_event(move |app, event| match event.id().as_ref() {
                     "update" => {
-                        let url = app.state::<AppState>()
-                            .update_available.lock().unwrap()
-                            .as_ref()
-                            .map(|info| info.url.clone());
-                        if let Some(url) = url {
-                            let _ = app.opener().open_url(&url, None::<&str>);
-                        }
+                        handle_update_click(app);
+                    }
+                    "skip_update" => {
+                        skip_update(app);
                     }
                     "refresh" => {
                         let app = app.clone();
@@ -579,16 +1699,41 @@ pub fn run() {
                             poll_usage(&app).await;
                         });
                     }
+                    "pause_polling" => {
+                        app.state::<AppState>()
+                            .polling_paused
+                            .fetch_xor(true, Ordering::Relaxed);
+                        refresh_tray_menu(app);
+                    }
+                    "acknowledge" => {
+                        acknowledge_alerts(app, None);
+                    }
+                    "snooze_alerts" => {
+                        toggle_alert_snooze(app);
+                    }
+                    "copy_status" => {
+                        copy_status_to_clipboard(app);
+                    }
                     "open_claude" => {
                         let _ = app.opener().open_url("https://claude.ai/settings/usage", None::<&str>);
                     }
+                    "report" => {
+                        show_report_window(app);
+                    }
                     "settings" => {
                         show_setup(app);
                     }
                     "quit" => {
                         std::process::exit(0);
                     }
-                    _ => {}
+                    id => {
+                        if let Some(index) = id
+                            .strip_prefix("profile:")
+                            .and_then(|i| i.parse::<usize>().ok())
+                        {
+                            switch_profile(app, index);
+                        }
+                    }
                 })
                 .on_tray_icon_event(|tray, event| {
                     if let TrayIconEvent::Click {
@@ -599,6 +1744,8 @@ pub fn run() {
                     } = event
                     {
                         let app = tray.app_handle();
+                        acknowledge_blink_click(app);
+
                         let visible = app.get_webview_window("popup")
                             .map(|w| w.is_visible().unwrap_or(false))
                             .unwrap_or(false);
@@ -614,29 +1761,68 @@ pub fn run() {
                 })
                 .build(app)?;
 
-            // Tray blink loop — toggles icon when RedBlink is active
+            // Tray blink loop — toggles icon when RedBlink is active.
+            // Parks on `blink_wake` while there is nothing to blink.
             {
                 let app_handle = app.handle().clone();
                 let blink_flag = blink_active.clone();
+                let blink_wake = blink_wake.clone();
                 tauri::async_runtime::spawn(async move {
+                    let state = app_handle.state::<AppState>();
                     let mut blink_on = true;
+                    let mut blinks = 0u32;
                     loop {
-                        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+                        let settings = state.config.lock().unwrap().blink;
+                        let interval = match settings.mode.interval() {
+                            Some(interval) if blink_flag.load(Ordering::Relaxed) => interval,
+                            _ => {
+                                if !blink_on {
+                                    blink_on = true;
+                                    restore_tray_icon(&app_handle);
+                                }
+                                blinks = 0;
+                                blink_wake.notified().await;
+                                continue;
+                            }
+                        };
+
+                        let reduced_motion =
+                            settings.respect_reduced_motion && system::prefers_reduced_motion();
+                        let away = system::user_idle_seconds()
+                            .is_some_and(|secs| secs >= BLINK_IDLE_SUSPEND_SECS);
+                        if reduced_motion || away {
+                            if !blink_on {
+                                blink_on = true;
+                                restore_tray_icon(&app_handle);
+                            }
+                            tokio::time::sleep(tokio::time::Duration::from_secs(BLINK_RECHECK_SECS))
+                                .await;
+                            continue;
+                        }
+
+                        tokio::time::sleep(interval).await;
                         if !blink_flag.load(Ordering::Relaxed) {
-                            blink_on = true;
                             continue;
                         }
                         blink_on = !blink_on;
+                        if blink_on {
+                            blinks += 1;
+                            if settings.max_blinks.is_some_and(|max| blinks >= max) {
+                                // Blinked enough — stay steady until the next escalation
+                                state.blink_suppressed.store(true, Ordering::Relaxed);
+                                blink_flag.store(false, Ordering::Relaxed);
+                            }
+                        }
                         if let Some(tray) = app_handle.tray_by_id("main-tray") {
                             if blink_on {
                                 // Show normal bars
-                                let state = app_handle.state::<AppState>();
                                 let usage_data = state.usage.lock().unwrap().clone();
                                 update_tray_icon(&tray, usage_data.as_ref());
                             } else {
                                 // Show dimmed/empty bars
+                                let theme = state.config.lock().unwrap().theme;
                                 let (rgba, w, h) = generate_bars_rgba(
-                                    0.0, UsageColor::Gray, 0.0, UsageColor::Gray,
+                                    theme, 0.0, UsageColor::Gray, 0.0, UsageColor::Gray,
                                 );
                                 let icon = Image::new_owned(rgba, w, h);
                                 let _ = tray.set_icon(Some(icon));
@@ -670,15 +1856,59 @@ pub fn run() {
                 start_polling_loop(app.handle());
             }
 
+            // Global shortcut to acknowledge alerts without opening the tray
+            app.handle().plugin(
+                tauri_plugin_global_shortcut::Builder::new()
+                    .with_handler(|app, _shortcut, event| {
+                        if event.state() == ShortcutState::Pressed {
+                            acknowledge_alerts(app, None);
+                        }
+                    })
+                    .build(),
+            )?;
+            if let Err(e) = app.global_shortcut().register(ACKNOWLEDGE_SHORTCUT) {
+                eprintln!("[shortcut] Failed to register {}: {}", ACKNOWLEDGE_SHORTCUT, e);
+            }
+
             // Check for updates in background
             start_update_check_loop(app.handle());
 
+            start_webhook_retry_loop(app.handle());
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                start_mqtt(&app_handle).await;
+            });
+
+            // Made it through startup — keep a freshly installed update
+            updater::confirm_startup();
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             get_usage,
+            get_usage_profile,
+            set_webhooks,
+            test_webhook,
+            set_hooks,
+            test_hook,
+            set_mqtt,
+            get_report,
+            export_report,
+            set_weekly_report,
             save_config,
             get_config,
+            set_theme,
+            set_blink_settings,
+            set_thresholds,
+            set_projection,
+            set_extra_usage_budget,
+            set_update_settings,
+            set_network_config,
+            set_api_endpoints,
+            get_update_details,
+            install_update,
+            skip_update_version,
+            acknowledge_alert,
             refresh_now,
             hide_popup,
         ])
@@ -0,0 +1,122 @@
+//! In-process stand-in for claude.ai's usage endpoint, shared by the API and poll tests.
+
+use crate::api::{ApiEndpoints, ClaudeClient};
+use crate::net::{NetworkConfig, ProxyMode};
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use wiremock::matchers::{header_regex, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+pub const ORG_ID: &str = "org-test";
+const USAGE_PATH: &str = "/api/organizations/org-test/usage";
+
+pub struct MockClaude {
+    server: MockServer,
+}
+
+impl MockClaude {
+    /// Start a server that rejects every request with 401 until responses are mounted.
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(401))
+            .with_priority(10)
+            .mount(&server)
+            .await;
+        Self { server }
+    }
+
+    /// Default endpoint paths on this server.
+    pub fn endpoints(&self) -> ApiEndpoints {
+        ApiEndpoints {
+            base_url: self.server.uri(),
+            ..ApiEndpoints::default()
+        }
+    }
+
+    pub fn client(&self, session_key: &str) -> ClaudeClient {
+        self.client_with(session_key, &self.endpoints())
+    }
+
+    /// A client for `endpoints`. Requests time out after a second to keep slow tests short.
+    pub fn client_with(&self, session_key: &str, endpoints: &ApiEndpoints) -> ClaudeClient {
+        let network = NetworkConfig {
+            proxy_mode: ProxyMode::Direct,
+            request_timeout_secs: 1,
+            ..NetworkConfig::default()
+        };
+        ClaudeClient::new(session_key, ORG_ID, endpoints, &network)
+    }
+
+    /// Answer usage requests that carry `session_key` (plain `[a-z0-9-]`) with `response`.
+    pub async fn respond(&self, session_key: &str, response: ResponseTemplate) {
+        self.respond_at(USAGE_PATH, session_key, response).await;
+    }
+
+    /// Like `respond`, for a usage endpoint at `path` (e.g. behind a relay).
+    pub async fn respond_at(&self, path: &str, session_key: &str, response: ResponseTemplate) {
+        usage_request(path, session_key)
+            .respond_with(response)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Like `respond`, but only for the next matching request.
+    pub async fn respond_once(&self, session_key: &str, response: ResponseTemplate) {
+        usage_request(USAGE_PATH, session_key)
+            .respond_with(response)
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Cookie headers of the requests received so far.
+    pub async fn cookies_sent(&self) -> Vec<String> {
+        self.server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .map(|r| {
+                r.headers
+                    .get("cookie")
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string()
+            })
+            .collect()
+    }
+}
+
+fn usage_request(usage_path: &str, session_key: &str) -> wiremock::MockBuilder {
+    Mock::given(method("GET"))
+        .and(path(usage_path))
+        .and(header_regex(
+            "cookie",
+            &format!("(^|; )sessionKey={}(;|$)", session_key),
+        ))
+}
+
+/// Usage response body with both windows an hour into their period.
+pub fn usage_body(session: f64, weekly: f64) -> Value {
+    let now = Utc::now();
+    json!({
+        "five_hour": { "utilization": session, "resets_at": (now + Duration::hours(4)).to_rfc3339() },
+        "seven_day": { "utilization": weekly, "resets_at": (now + Duration::hours(167)).to_rfc3339() },
+        "seven_day_opus": null,
+        "extra_usage": null
+    })
+}
+
+pub fn usage(session: f64, weekly: f64) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(usage_body(session, weekly))
+}
+
+/// A usage response that also rotates the session key, as claude.ai does now and then.
+pub fn rotating_usage(session: f64, weekly: f64, new_key: &str) -> ResponseTemplate {
+    usage(session, weekly).insert_header(
+        "set-cookie",
+        format!("sessionKey={}; Path=/; HttpOnly; SameSite=Lax", new_key).as_str(),
+    )
+}
@@ -0,0 +1,504 @@
+//! MQTT publisher for home-automation dashboards, with Home Assistant discovery.
+//!
+//! Each bucket's state goes to `{topic_prefix}/{bucket}` as retained JSON, and
+//! `{topic_prefix}/status` says whether the app is online (a last will covers crashes).
+
+use crate::usage::{BucketKind, UsageBar, UsageState};
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Outgoing, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Wait this long before reconnecting after the broker goes away.
+const RECONNECT_DELAY_SECS: u64 = 5;
+/// Requests queued for the broker before `publish` starts dropping them.
+const REQUEST_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttSettings {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    /// Saved in the OS keychain, never in the settings store.
+    pub password: Option<String>,
+    /// Keychain entry holding `password`, which is all the settings store keeps of it.
+    pub password_ref: Option<String>,
+    /// Must be unique on the broker, which drops the older of two connections with the same ID.
+    pub client_id: String,
+    /// Bucket states go to `{topic_prefix}/session` and `{topic_prefix}/weekly`.
+    pub topic_prefix: String,
+    /// Announce the sensors to Home Assistant under `discovery_prefix`.
+    pub discovery: bool,
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 1883,
+            username: None,
+            password: None,
+            password_ref: None,
+            client_id: default_client_id(),
+            topic_prefix: "tokentorch".to_string(),
+            discovery: true,
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+/// One message for the broker; everything the app publishes is retained.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: String,
+}
+
+impl MqttSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.host.trim().is_empty() {
+            return Err("MQTT broker host can't be empty".to_string());
+        }
+        if self.port == 0 {
+            return Err("MQTT broker port can't be 0".to_string());
+        }
+        if self.client_id.trim().is_empty() {
+            return Err("MQTT client ID can't be empty".to_string());
+        }
+        for (name, topic) in [
+            ("Topic prefix", &self.topic_prefix),
+            ("Discovery prefix", &self.discovery_prefix),
+        ] {
+            if topic.is_empty() || topic.starts_with('/') || topic.ends_with('/') {
+                return Err(format!("{} can't be empty or start or end with /", name));
+            }
+            if topic.contains(['+', '#']) {
+                return Err(format!("{} can't contain MQTT wildcards", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// The settings store's copy: the password is left out, its keychain reference kept.
+    pub fn without_password(&self) -> Self {
+        Self {
+            password: None,
+            ..self.clone()
+        }
+    }
+
+    pub fn status_topic(&self) -> String {
+        format!("{}/status", self.topic_prefix)
+    }
+
+    pub fn state_topic(&self, kind: BucketKind) -> String {
+        format!("{}/{}", self.topic_prefix, bucket_id(kind))
+    }
+
+    pub fn state_message(&self, bar: &UsageBar) -> Message {
+        let payload = json!({
+            "label": bar.label,
+            "utilization": bar.utilization,
+            "projected": bar.projected,
+            "color": format!("{:?}", bar.color).to_lowercase(),
+            "resets_at": bar.resets_at,
+            "limit_at": bar.limit_at,
+        });
+        Message {
+            topic: self.state_topic(bar.kind),
+            payload: payload.to_string(),
+        }
+    }
+
+    /// Home Assistant sensor configs for one bucket: utilization, projection, color and reset time.
+    pub fn discovery_messages(&self, kind: BucketKind) -> Vec<Message> {
+        let node = self.node_id();
+        let bucket = bucket_id(kind);
+        let device = json!({
+            "identifiers": [node],
+            "name": "TokenTorch",
+            "manufacturer": "TokenTorch",
+            "sw_version": env!("CARGO_PKG_VERSION"),
+        });
+        let sensors = [
+            ("utilization", "usage", Some("%"), None),
+            ("projected", "projected usage", Some("%"), None),
+            ("color", "color", None, None),
+            ("resets_at", "reset", None, Some("timestamp")),
+        ];
+        sensors
+            .into_iter()
+            .map(|(field, name, unit, device_class)| {
+                let mut config = json!({
+                    "name": format!("{} {}", kind.label(), name),
+                    "unique_id": format!("{}_{}_{}", node, bucket, field),
+                    "state_topic": self.state_topic(kind),
+                    "value_template": format!("{{{{ value_json.{} }}}}", field),
+                    "availability_topic": self.status_topic(),
+                    "device": device,
+                });
+                if let Some(unit) = unit {
+                    config["unit_of_measurement"] = json!(unit);
+                    config["state_class"] = json!("measurement");
+                }
+                if let Some(device_class) = device_class {
+                    config["device_class"] = json!(device_class);
+                }
+                Message {
+                    topic: format!(
+                        "{}/sensor/{}/{}_{}/config",
+                        self.discovery_prefix, node, bucket, field
+                    ),
+                    payload: config.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    /// Home Assistant object ID for this app instance, from the topic prefix.
+    fn node_id(&self) -> String {
+        self.topic_prefix
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    }
+
+    fn options(&self) -> MqttOptions {
+        let mut options = MqttOptions::new(&self.client_id, &self.host, self.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(
+            self.status_topic(),
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some(username) = self.username.as_deref().filter(|u| !u.is_empty()) {
+            options.set_credentials(username, self.password.clone().unwrap_or_default());
+        }
+        options
+    }
+}
+
+/// A broker connection that publishes each poll's bucket states. Dropping it marks the app
+/// offline and disconnects.
+pub struct MqttPublisher {
+    settings: MqttSettings,
+    client: AsyncClient,
+    /// Buckets whose discovery configs were sent on this connection.
+    announced: Vec<BucketKind>,
+    /// Tells the connection to stop retrying a broker that's down.
+    stopped: Arc<AtomicBool>,
+}
+
+impl MqttPublisher {
+    /// Connect to the broker in `settings`. The returned future keeps the connection up
+    /// (reconnecting as needed) and must be spawned; it ends when the publisher is dropped.
+    pub fn start(settings: MqttSettings) -> (Self, impl Future<Output = ()> + Send + 'static) {
+        let (client, eventloop) = AsyncClient::new(settings.options(), REQUEST_CAPACITY);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let connection = run(
+            eventloop,
+            client.clone(),
+            settings.status_topic(),
+            stopped.clone(),
+        );
+        let publisher = Self {
+            settings,
+            client,
+            announced: Vec::new(),
+            stopped,
+        };
+        (publisher, connection)
+    }
+
+    pub fn settings(&self) -> &MqttSettings {
+        &self.settings
+    }
+
+    /// Queue the state of every bucket in `state`, announcing new buckets to Home Assistant first.
+    pub fn publish(&mut self, state: &UsageState) {
+        for bar in state.bars() {
+            if self.settings.discovery && !self.announced.contains(&bar.kind) {
+                self.announced.push(bar.kind);
+                for message in self.settings.discovery_messages(bar.kind) {
+                    self.send(message);
+                }
+            }
+            self.send(self.settings.state_message(bar));
+        }
+    }
+
+    fn send(&self, message: Message) {
+        if let Err(e) =
+            self.client
+                .try_publish(message.topic, QoS::AtLeastOnce, true, message.payload)
+        {
+            eprintln!("[mqtt] publish dropped: {}", e);
+        }
+    }
+}
+
+impl Drop for MqttPublisher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.send(Message {
+            topic: self.settings.status_topic(),
+            payload: "offline".to_string(),
+        });
+        if let Err(e) = self.client.try_disconnect() {
+            eprintln!("[mqtt] disconnect failed: {}", e);
+        }
+    }
+}
+
+/// Drive the connection, marking the app online after every (re)connect.
+async fn run(
+    mut eventloop: EventLoop,
+    client: AsyncClient,
+    status_topic: String,
+    stopped: Arc<AtomicBool>,
+) {
+    // Log the first failure, not every retry while the broker is down
+    let mut failing = false;
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                eprintln!("[mqtt] connected");
+                failing = false;
+                if let Err(e) = client.try_publish(&status_topic, QoS::AtLeastOnce, true, "online")
+                {
+                    eprintln!("[mqtt] publish dropped: {}", e);
+                }
+            }
+            Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+            Ok(_) => {}
+            Err(_) if stopped.load(Ordering::Relaxed) => break,
+            Err(e) => {
+                if !failing {
+                    eprintln!("[mqtt] connection error: {}", e);
+                    failing = true;
+                }
+                tokio::time::sleep(Duration::from_secs(RECONNECT_DELAY_SECS)).await;
+            }
+        }
+    }
+}
+
+/// "tokentorch-" and this computer's name, so two machines on one broker don't keep
+/// kicking each other off.
+pub fn default_client_id() -> String {
+    let host: String = hostname()
+        .unwrap_or_default()
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    if host.is_empty() {
+        "tokentorch".to_string()
+    } else {
+        format!("tokentorch-{}", host)
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..end]).into_owned())
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+fn bucket_id(kind: BucketKind) -> &'static str {
+    match kind {
+        BucketKind::Session => "session",
+        BucketKind::Weekly => "weekly",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use crate::usage::UsageColor;
+    use bytes::BytesMut;
+    use rumqttc::mqttbytes::v4::{self, ConnAck, ConnectReturnCode, PubAck};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    fn bar(kind: BucketKind, utilization: f64, color: UsageColor) -> UsageBar {
+        UsageBar {
+            utilization,
+            ..test_support::bar(kind, color)
+        }
+    }
+
+    fn usage_state() -> UsageState {
+        UsageState {
+            session: Some(bar(BucketKind::Session, 42.0, UsageColor::Green)),
+            weekly: Some(bar(BucketKind::Weekly, 90.0, UsageColor::Red)),
+            last_updated: String::new(),
+            error: None,
+            extra: None,
+            plan: None,
+            other_buckets: Vec::new(),
+        }
+    }
+
+    /// Accept one client, ack its connect and publishes, and forward each publish as
+    /// (topic, payload, retain) until it disconnects.
+    async fn broker(
+        listener: TcpListener,
+        published: mpsc::UnboundedSender<(String, String, bool)>,
+    ) {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = BytesMut::new();
+        loop {
+            match v4::read(&mut buf, 1 << 20) {
+                Ok(v4::Packet::Connect(_)) => {
+                    let mut out = BytesMut::new();
+                    ConnAck::new(ConnectReturnCode::Success, false)
+                        .write(&mut out)
+                        .unwrap();
+                    socket.write_all(&out).await.unwrap();
+                }
+                Ok(v4::Packet::Publish(publish)) => {
+                    let mut out = BytesMut::new();
+                    PubAck::new(publish.pkid).write(&mut out).unwrap();
+                    // The client may already be gone after its last publish
+                    let _ = socket.write_all(&out).await;
+                    let payload = String::from_utf8(publish.payload.to_vec()).unwrap();
+                    let _ = published.send((publish.topic, payload, publish.retain));
+                }
+                Ok(v4::Packet::Disconnect) => return,
+                Ok(_) => {}
+                Err(rumqttc::mqttbytes::Error::InsufficientBytes(_)) => {
+                    if !matches!(socket.read_buf(&mut buf).await, Ok(n) if n > 0) {
+                        return;
+                    }
+                }
+                Err(e) => panic!("bad packet: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn discovery_points_home_assistant_at_the_state_topic() {
+        let settings = MqttSettings {
+            topic_prefix: "desk/Claude".to_string(),
+            ..MqttSettings::default()
+        };
+        let configs = settings.discovery_messages(BucketKind::Weekly);
+        assert_eq!(configs.len(), 4);
+        assert_eq!(
+            configs[0].topic,
+            "homeassistant/sensor/desk_claude/weekly_utilization/config"
+        );
+        let config: serde_json::Value = serde_json::from_str(&configs[0].payload).unwrap();
+        assert_eq!(config["name"], "Weekly usage");
+        assert_eq!(config["state_topic"], "desk/Claude/weekly");
+        assert_eq!(config["value_template"], "{{ value_json.utilization }}");
+        assert_eq!(config["unit_of_measurement"], "%");
+        assert_eq!(config["availability_topic"], "desk/Claude/status");
+        let reset: serde_json::Value = serde_json::from_str(&configs[3].payload).unwrap();
+        assert_eq!(reset["device_class"], "timestamp");
+
+        let state = settings.state_message(&bar(BucketKind::Weekly, 90.0, UsageColor::RedBlink));
+        let payload: serde_json::Value = serde_json::from_str(&state.payload).unwrap();
+        assert_eq!(payload["color"], "redblink");
+        assert_eq!(payload["utilization"], 90.0);
+
+        let wildcard = MqttSettings {
+            topic_prefix: "desk/#".to_string(),
+            ..MqttSettings::default()
+        };
+        assert_eq!(
+            wildcard.validate().unwrap_err(),
+            "Topic prefix can't contain MQTT wildcards"
+        );
+    }
+
+    #[test]
+    fn default_client_id_names_this_computer() {
+        let client_id = MqttSettings::default().client_id;
+        assert!(client_id.starts_with("tokentorch"), "{}", client_id);
+        assert!(client_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'), "{}", client_id);
+        assert!(MqttSettings::default().validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn publishes_retained_states_to_a_broker() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let broker = tokio::spawn(broker(listener, tx));
+
+        let (mut publisher, connection) = MqttPublisher::start(MqttSettings {
+            enabled: true,
+            host: "127.0.0.1".to_string(),
+            port,
+            ..MqttSettings::default()
+        });
+        let connection = tokio::spawn(connection);
+        publisher.publish(&usage_state());
+        // Discovery goes out once per bucket
+        publisher.publish(&usage_state());
+
+        let mut messages = Vec::new();
+        while messages.len() < 2 * 4 + 2 * 2 + 1 {
+            messages.push(rx.recv().await.unwrap());
+        }
+        assert!(messages.iter().all(|(_, _, retain)| *retain));
+        let topics: Vec<&str> = messages
+            .iter()
+            .map(|(topic, _, _)| topic.as_str())
+            .collect();
+        assert_eq!(
+            topics
+                .iter()
+                .filter(|t| t.starts_with("homeassistant/"))
+                .count(),
+            8
+        );
+        assert_eq!(
+            topics.iter().filter(|t| **t == "tokentorch/weekly").count(),
+            2
+        );
+        assert!(messages.contains(&("tokentorch/status".to_string(), "online".to_string(), true)));
+        let (_, session, _) = messages
+            .iter()
+            .find(|(t, _, _)| t == "tokentorch/session")
+            .unwrap();
+        assert!(session.contains("\"color\":\"green\""));
+
+        drop(publisher);
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            ("tokentorch/status".to_string(), "offline".to_string(), true)
+        );
+        connection.await.unwrap();
+        broker.await.unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+//! Whether the plan's capacity fits, from how past windows would have gone with more or less of it.
+
+use crate::history::{self, Sample};
+use crate::usage::{online_seconds_between, BucketKind};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Capacity relative to the current plan; 5x and 20x are the Max tiers over Pro.
+const MULTIPLIERS: [f64; 6] = [0.2, 0.5, 1.0, 2.0, 5.0, 20.0];
+/// Less history than this and there's no recommendation.
+const MIN_HISTORY_DAYS: f64 = 3.0;
+/// A smaller plan is only suggested if this much less capacity would still have done.
+const OVER_PROVISIONED_BELOW: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PlanFit {
+    UnderProvisioned,
+    OverProvisioned,
+    Fits,
+}
+
+/// How the recorded windows would have gone with `multiplier` times the capacity.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanScenario {
+    pub multiplier: f64,
+    pub limit_hits: usize,
+    pub highest_peak: f64,
+    pub average_peak: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanAnalysis {
+    /// Days of history analyzed.
+    pub days: f64,
+    pub windows: usize,
+    /// One per multiplier, smallest capacity first; the current plan is 1.0.
+    pub scenarios: Vec<PlanScenario>,
+    pub fit: PlanFit,
+    /// Smallest capacity without limit hits, when it differs from the current plan.
+    pub suggested_multiplier: Option<f64>,
+    /// E.g. "3 limit hits in 28 days; 0 on a plan with 5x capacity".
+    pub display: String,
+}
+
+impl PlanAnalysis {
+    pub fn current(&self) -> &PlanScenario {
+        self.scenarios
+            .iter()
+            .find(|s| s.multiplier == 1.0)
+            .expect("1.0 is always simulated")
+    }
+}
+
+/// Simulate every multiplier over the session and weekly windows in `samples`, or None
+/// with too little history.
+///
+/// A window that hit the limit is assumed to have kept its pre-hit burn rate through the
+/// blocked time, so its demand on a bigger plan is more than the 100% that was observed.
+pub fn analyze(samples: &[Sample], now: DateTime<Utc>) -> Option<PlanAnalysis> {
+    let first = samples.first()?.at;
+    let days = (now - first).num_minutes() as f64 / (24.0 * 60.0);
+    if days < MIN_HISTORY_DAYS {
+        return None;
+    }
+
+    let demands: Vec<f64> = [BucketKind::Session, BucketKind::Weekly]
+        .into_iter()
+        .flat_map(|kind| {
+            history::group_windows(samples, kind)
+                .into_iter()
+                .map(move |window| window_demand(kind, &window, now))
+        })
+        .collect();
+    if demands.is_empty() {
+        return None;
+    }
+
+    let scenarios: Vec<PlanScenario> = MULTIPLIERS
+        .iter()
+        .map(|&multiplier| {
+            let peaks: Vec<f64> = demands.iter().map(|d| (d / multiplier).min(100.0)).collect();
+            PlanScenario {
+                multiplier,
+                limit_hits: peaks.iter().filter(|p| **p >= 100.0).count(),
+                highest_peak: peaks.iter().copied().fold(0.0, f64::max),
+                average_peak: peaks.iter().sum::<f64>() / peaks.len() as f64,
+            }
+        })
+        .collect();
+
+    let hits_at = |m: f64| scenarios.iter().find(|s| s.multiplier == m).map_or(0, |s| s.limit_hits);
+    let current_hits = hits_at(1.0);
+    let span = format!("{:.0} days", days);
+    let (fit, suggested_multiplier, display) = if current_hits > 0 {
+        let bigger = scenarios.iter().find(|s| s.multiplier > 1.0 && s.limit_hits == 0);
+        let display = match bigger {
+            Some(s) => format!(
+                "{} in {}; 0 on a plan with {}x capacity",
+                hits(current_hits),
+                span,
+                s.multiplier
+            ),
+            None => {
+                let largest = scenarios.last().expect("multipliers aren't empty");
+                format!(
+                    "{} in {}; still {} on a plan with {}x capacity",
+                    hits(current_hits),
+                    span,
+                    largest.limit_hits,
+                    largest.multiplier
+                )
+            }
+        };
+        (PlanFit::UnderProvisioned, bigger.map(|s| s.multiplier), display)
+    } else {
+        match scenarios
+            .iter()
+            .find(|s| s.multiplier <= OVER_PROVISIONED_BELOW && s.limit_hits == 0)
+        {
+            Some(s) => (
+                PlanFit::OverProvisioned,
+                Some(s.multiplier),
+                format!("No limit hits in {}; 0 on a plan with {}x capacity too", span, s.multiplier),
+            ),
+            None => (
+                PlanFit::Fits,
+                None,
+                format!(
+                    "Plan fits: no limit hits in {}, {} with half the capacity",
+                    span,
+                    hits_at(OVER_PROVISIONED_BELOW)
+                ),
+            ),
+        }
+    };
+
+    Some(PlanAnalysis {
+        days,
+        windows: demands.len(),
+        scenarios,
+        fit,
+        suggested_multiplier,
+        display,
+    })
+}
+
+/// Percent of the current plan the window would have used without a limit.
+fn window_demand(kind: BucketKind, window: &[&Sample], now: DateTime<Utc>) -> f64 {
+    let peak = window.iter().map(|s| s.utilization).fold(0.0, f64::max);
+    let Some(hit) = window.iter().find(|s| s.utilization >= 100.0) else {
+        return peak;
+    };
+    let Ok(reset) = hit.resets_at.parse::<DateTime<Utc>>() else {
+        return peak;
+    };
+    let window_start = reset - Duration::minutes((kind.window_hours() * 60.0) as i64);
+    let before = online_seconds_between(window_start, hit.at);
+    let blocked = online_seconds_between(hit.at, reset.min(now));
+    if before <= 0.0 {
+        return peak;
+    }
+    peak + 100.0 * blocked / before
+}
+
+fn hits(n: usize) -> String {
+    match n {
+        1 => "1 limit hit".to_string(),
+        n => format!("{} limit hits", n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{local, sample};
+
+    /// One session window per weekday from 9:00 to 14:00, polled at 9, 11 and 13.
+    fn sessions(days: std::ops::Range<u32>, at_11: f64, at_13: f64) -> Vec<Sample> {
+        days.flat_map(|day| {
+            [(9, 0.0), (11, at_11), (13, at_13)].map(|(hour, utilization)| {
+                sample(local(day, hour), BucketKind::Session, utilization, local(day, 14))
+            })
+        })
+        .collect()
+    }
+
+    #[test]
+    fn limit_hits_suggest_a_bigger_plan() {
+        // Out by 11:00 every day, then blocked until 14:00
+        let analysis = analyze(&sessions(12..17, 100.0, 100.0), local(17, 18)).unwrap();
+        assert_eq!(analysis.fit, PlanFit::UnderProvisioned);
+        assert_eq!(analysis.current().limit_hits, 5);
+        // 100% in 2 hours, 3 more blocked hours: 250% of today's plan each day
+        let at_2x = &analysis.scenarios[3];
+        assert_eq!((at_2x.multiplier, at_2x.limit_hits), (2.0, 5));
+        assert_eq!(analysis.suggested_multiplier, Some(5.0));
+        assert_eq!(analysis.display, "5 limit hits in 5 days; 0 on a plan with 5x capacity");
+    }
+
+    #[test]
+    fn light_usage_suggests_a_smaller_plan_and_needs_history() {
+        let light = analyze(&sessions(12..17, 5.0, 10.0), local(17, 18)).unwrap();
+        assert_eq!(light.fit, PlanFit::OverProvisioned);
+        assert_eq!(light.suggested_multiplier, Some(0.2));
+        assert_eq!(light.display, "No limit hits in 5 days; 0 on a plan with 0.2x capacity too");
+
+        let busy = analyze(&sessions(12..17, 40.0, 70.0), local(17, 18)).unwrap();
+        assert_eq!(busy.fit, PlanFit::Fits);
+        assert_eq!(busy.display, "Plan fits: no limit hits in 5 days, 5 with half the capacity");
+
+        assert_eq!(analyze(&sessions(12..14, 5.0, 10.0), local(14, 18)), None);
+    }
+}
@@ -0,0 +1,215 @@
+//! One usage poll without the tray: fetch, compute bars, apply acknowledgments, rotate the key.
+
+use crate::alerts::BucketMarks;
+use crate::api::ClaudeClient;
+use crate::events::{Event, EventLog};
+use crate::history::History;
+use crate::plan;
+use crate::profile::UsageProfile;
+use crate::usage::{self, ProjectionMode, ThresholdPolicy, UsageState};
+use std::sync::Mutex;
+
+/// What polls carry over from one to the next. The default starts empty and keeps
+/// everything in memory.
+#[derive(Debug, Default)]
+pub struct PollContext {
+    /// Per-bucket "seen it" from the user.
+    pub acknowledgments: Mutex<BucketMarks>,
+    /// Past usage samples; their burn-rate spread sets the projection bands.
+    pub history: Mutex<History>,
+    /// Window resets and limit hits seen so far.
+    pub events: Mutex<EventLog>,
+}
+
+#[derive(Debug)]
+pub struct PollOutcome {
+    /// Bars to show, or an error state if the fetch failed.
+    pub state: UsageState,
+    /// claude.ai rotated the session key; the client already uses it, the caller persists it.
+    pub refreshed_session_key: Option<String>,
+    /// Acknowledgments expired (color change or window reset) and should be saved.
+    pub acknowledgments_changed: bool,
+    /// Resets and limit hits this poll noticed.
+    pub events: Vec<Event>,
+}
+
+impl PollOutcome {
+    pub fn is_ok(&self) -> bool {
+        self.state.error.is_none()
+    }
+}
+
+/// `extra_budget` is the user's monthly ceiling for extra-usage spend, if set. Successful
+/// polls are added to the context's history under the client's org, whose samples for that
+/// org widen or narrow the projections through their burn-rate spread and, with `projection`
+/// set to learned, weight the time left through their usage profile. Resets and limit hits
+/// found along the way go to its events, and the plan analysis runs over the org's history.
+pub async fn poll_once(
+    client: &ClaudeClient,
+    policy: &ThresholdPolicy,
+    projection: ProjectionMode,
+    extra_budget: Option<f64>,
+    context: &PollContext,
+) -> PollOutcome {
+    let result = match client.fetch_usage().await {
+        Ok(result) => result,
+        Err(err) => {
+            return PollOutcome {
+                state: UsageState {
+                    session: None,
+                    weekly: None,
+                    last_updated: chrono::Utc::now().to_rfc3339(),
+                    error: Some(err),
+                    extra: None,
+                    plan: None,
+                    other_buckets: Vec::new(),
+                },
+                refreshed_session_key: None,
+                acknowledgments_changed: false,
+                events: Vec::new(),
+            }
+        }
+    };
+
+    let now = chrono::Utc::now();
+    let org_id = client.org_id();
+    let (mut state, new_events) = {
+        let mut history = context.history.lock().unwrap();
+        let new_events = context.events.lock().unwrap().observe(&history, org_id, &result.usage, now);
+        history.record(org_id, &result.usage, now);
+        let profile = match projection {
+            ProjectionMode::Learned => UsageProfile::learn(history.samples(org_id)),
+            ProjectionMode::OnlineHours => None,
+        };
+        let mut state = usage::compute_state(
+            &result.usage,
+            policy,
+            |kind| history.rate_spread(org_id, kind, now),
+            profile.as_ref(),
+        );
+        state.plan = plan::analyze(history.samples(org_id), now);
+        (state, new_events)
+    };
+    state.extra = result.usage.extra_usage.as_ref().and_then(|extra| {
+        usage::compute_extra_spend(extra, extra_budget, now)
+    });
+    context.events.lock().unwrap().apply(org_id, &mut state, now);
+
+    // Acknowledgments end on color change or window reset
+    let acknowledgments_changed = {
+        let mut acks = context.acknowledgments.lock().unwrap();
+        let changed = acks.prune(&state);
+        acks.apply(&mut state);
+        changed
+    };
+
+    if let Some(new_key) = &result.refreshed_session_key {
+        client.update_session_key(new_key.clone());
+    }
+
+    PollOutcome {
+        state,
+        refreshed_session_key: result.refreshed_session_key,
+        acknowledgments_changed,
+        events: new_events,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_claude::{self, MockClaude};
+    use crate::usage::UsageColor;
+    use wiremock::ResponseTemplate;
+
+    #[tokio::test]
+    async fn poll_follows_session_key_rotation() {
+        let mock = MockClaude::start().await;
+        mock.respond_once("sk-one", mock_claude::rotating_usage(20.0, 10.0, "sk-two"))
+            .await;
+        mock.respond("sk-two", mock_claude::usage(25.0, 11.0)).await;
+
+        let client = mock.client("sk-one");
+        let policy = ThresholdPolicy::default();
+        let context = PollContext::default();
+
+        let first = poll_once(&client, &policy, ProjectionMode::default(), None, &context).await;
+        assert!(first.is_ok(), "{:?}", first.state.error);
+        assert_eq!(first.refreshed_session_key.as_deref(), Some("sk-two"));
+        assert_eq!(client.session_key(), "sk-two");
+
+        let second = poll_once(&client, &policy, ProjectionMode::default(), None, &context).await;
+        assert!(second.is_ok(), "{:?}", second.state.error);
+        assert_eq!(second.refreshed_session_key, None);
+        assert_eq!(second.state.session.unwrap().utilization, 25.0);
+
+        // The old key is no longer accepted
+        let third = poll_once(&mock.client("sk-one"), &policy, ProjectionMode::default(), None, &context).await;
+        assert!(!third.is_ok());
+    }
+
+    #[tokio::test]
+    async fn acknowledgment_expires_when_color_changes() {
+        let mock = MockClaude::start().await;
+        mock.respond_once("sk", mock_claude::usage(99.0, 10.0)).await;
+        mock.respond("sk", mock_claude::usage(20.0, 10.0)).await;
+
+        let client = mock.client("sk");
+        let policy = ThresholdPolicy::default();
+        let context = PollContext::default();
+
+        let alert = poll_once(&client, &policy, ProjectionMode::default(), None, &context).await;
+        let session = alert.state.session.unwrap();
+        assert_ne!(session.color, UsageColor::Green);
+        context.acknowledgments.lock().unwrap().mark(&session);
+
+        let calm = poll_once(&client, &policy, ProjectionMode::default(), None, &context).await;
+        assert!(calm.acknowledgments_changed);
+        assert!(!calm.state.session.unwrap().acknowledged);
+    }
+
+    #[tokio::test]
+    async fn failed_poll_reports_error_state() {
+        let mock = MockClaude::start().await;
+        mock.respond("sk", ResponseTemplate::new(503)).await;
+
+        let outcome = poll_once(
+            &mock.client("sk"),
+            &ThresholdPolicy::default(),
+            ProjectionMode::default(),
+            None,
+            &PollContext::default(),
+        )
+        .await;
+        assert!(outcome.state.session.is_none());
+        assert_eq!(outcome.state.error.as_deref(), Some("API error: HTTP 503 Service Unavailable"));
+    }
+
+    #[tokio::test]
+    async fn extra_usage_spend_is_checked_against_budget() {
+        let mock = MockClaude::start().await;
+        let mut body = mock_claude::usage_body(10.0, 10.0);
+        body["extra_usage"] = serde_json::json!({
+            "is_enabled": true,
+            "monthly_limit": 100000,
+            "used_credits": 150000,
+            "utilization": 150.0
+        });
+        mock.respond("sk", ResponseTemplate::new(200).set_body_json(body))
+            .await;
+
+        let outcome = poll_once(
+            &mock.client("sk"),
+            &ThresholdPolicy::default(),
+            ProjectionMode::default(),
+            Some(20.0),
+            &PollContext::default(),
+        )
+        .await;
+        let extra = outcome.state.extra.unwrap();
+        assert_eq!(extra.spent, 1500.0);
+        assert_eq!(extra.remaining, Some(0.0));
+        assert!(extra.over_budget);
+        assert_eq!(extra.color, UsageColor::Red);
+    }
+}
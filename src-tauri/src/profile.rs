@@ -0,0 +1,203 @@
+//! When in the week the user tends to use Claude, learned from recorded history.
+
+use crate::alerts::same_window;
+use crate::history::Sample;
+use crate::usage::BucketKind;
+use chrono::{DateTime, Datelike, Duration, Local, Timelike, Utc};
+use serde::Serialize;
+
+const HOURS_PER_WEEK: usize = 7 * 24;
+/// Gaps longer than this (app closed, machine asleep) don't say when the usage happened.
+const MAX_PAIR_GAP_HOURS: i64 = 3;
+/// Each hour-of-week bin is pulled toward the same hour on other days, and each hour of the
+/// day toward the online-hours prior, as if the prior were this many hours of data.
+const PRIOR_HOURS: f64 = 2.0;
+/// Less observed time than this and projections stay on online hours.
+const MIN_OBSERVED_HOURS: f64 = 24.0;
+/// Same online hours as `usage`: 8:00–22:00 local.
+const PRIOR_ONLINE_HOURS: std::ops::Range<usize> = 8..22;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageProfile {
+    /// Share of a typical week's usage in each local hour, Monday 0:00 first; sums to 1.
+    pub weights: Vec<f64>,
+    /// Hours of history the profile was learned from.
+    pub observed_hours: f64,
+}
+
+impl UsageProfile {
+    /// Learn from consecutive same-window samples of each bucket, or None without enough history.
+    pub fn learn(samples: &[Sample]) -> Option<Self> {
+        let mut learned: Vec<(Vec<f64>, f64)> = Vec::new();
+        for kind in [BucketKind::Session, BucketKind::Weekly] {
+            let mut used = vec![0.0; HOURS_PER_WEEK];
+            let mut seconds = vec![0.0; HOURS_PER_WEEK];
+            let of_kind: Vec<&Sample> = samples.iter().filter(|s| s.kind == kind).collect();
+            for pair in of_kind.windows(2) {
+                let (a, b) = (pair[0], pair[1]);
+                let gap = b.at - a.at;
+                let du = b.utilization - a.utilization;
+                if gap <= Duration::zero()
+                    || gap > Duration::hours(MAX_PAIR_GAP_HOURS)
+                    || du < 0.0
+                    || !same_window(&a.resets_at, &b.resets_at)
+                {
+                    continue;
+                }
+                let total = gap.num_milliseconds() as f64 / 1000.0;
+                for (bin, piece_start, piece_end) in hour_pieces(a.at).take_while(|p| p.1 < b.at) {
+                    let piece = (piece_end.min(b.at) - piece_start).num_milliseconds() as f64 / 1000.0;
+                    seconds[bin] += piece;
+                    used[bin] += du * piece / total;
+                }
+            }
+
+            let observed_hours = seconds.iter().sum::<f64>() / 3600.0;
+            let total_used: f64 = used.iter().sum();
+            if observed_hours < MIN_OBSERVED_HOURS || total_used <= 0.0 {
+                continue;
+            }
+            // Unseen hours fall back to the same hour on other days, then to online hours
+            let mean_rate = total_used / observed_hours;
+            let day_rates: Vec<f64> = (0..24)
+                .map(|hour| {
+                    let prior = if PRIOR_ONLINE_HOURS.contains(&hour) { mean_rate } else { 0.0 };
+                    let bins = (0..7).map(|day| day * 24 + hour);
+                    let used: f64 = bins.clone().map(|bin| used[bin]).sum();
+                    let hours: f64 = bins.map(|bin| seconds[bin] / 3600.0).sum();
+                    (used + PRIOR_HOURS * prior) / (hours + PRIOR_HOURS)
+                })
+                .collect();
+            let rates: Vec<f64> = (0..HOURS_PER_WEEK)
+                .map(|bin| {
+                    let prior = day_rates[bin % 24];
+                    (used[bin] + PRIOR_HOURS * prior) / (seconds[bin] / 3600.0 + PRIOR_HOURS)
+                })
+                .collect();
+            let sum: f64 = rates.iter().sum();
+            learned.push((rates.iter().map(|r| r / sum).collect(), observed_hours));
+        }
+
+        if learned.is_empty() {
+            return None;
+        }
+        let n = learned.len() as f64;
+        let weights = (0..HOURS_PER_WEEK)
+            .map(|bin| learned.iter().map(|(w, _)| w[bin]).sum::<f64>() / n)
+            .collect();
+        let observed_hours = learned.iter().map(|(_, h)| *h).fold(0.0, f64::max);
+        Some(Self {
+            weights,
+            observed_hours,
+        })
+    }
+
+    /// Seconds between `start` and `end`, each scaled by how busy its hour of the week is;
+    /// an average hour counts 3600.
+    pub fn weighted_seconds_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> f64 {
+        if end <= start {
+            return 0.0;
+        }
+        hour_pieces(start)
+            .take_while(|p| p.1 < end)
+            .map(|(bin, piece_start, piece_end)| {
+                let piece = (piece_end.min(end) - piece_start).num_milliseconds() as f64 / 1000.0;
+                piece * self.scale(bin)
+            })
+            .sum()
+    }
+
+    /// The moment `seconds` of weighted time have passed after `start`, within `max_hours`.
+    pub fn advance_weighted(
+        &self,
+        start: DateTime<Utc>,
+        seconds: f64,
+        max_hours: usize,
+    ) -> Option<DateTime<Utc>> {
+        let mut remaining = seconds.max(0.0);
+        for (bin, piece_start, piece_end) in hour_pieces(start).take(max_hours + 1) {
+            let scale = self.scale(bin);
+            let piece = (piece_end - piece_start).num_milliseconds() as f64 / 1000.0;
+            if scale > 0.0 && remaining <= piece * scale {
+                return Some(piece_start + Duration::seconds((remaining / scale).ceil() as i64));
+            }
+            remaining -= piece * scale;
+        }
+        None
+    }
+
+    fn scale(&self, bin: usize) -> f64 {
+        self.weights[bin] * HOURS_PER_WEEK as f64
+    }
+}
+
+/// Local hour-of-week bin of `at`, Monday 0:00 first.
+fn hour_of_week(at: DateTime<Utc>) -> usize {
+    let local = at.with_timezone(&Local);
+    local.weekday().num_days_from_monday() as usize * 24 + local.hour() as usize
+}
+
+/// Consecutive (bin, start, end) pieces from `start`, split at local hour boundaries.
+fn hour_pieces(start: DateTime<Utc>) -> impl Iterator<Item = (usize, DateTime<Utc>, DateTime<Utc>)> {
+    std::iter::successors(Some(start), |&cursor| Some(next_hour(cursor)))
+        .map(|cursor| (hour_of_week(cursor), cursor, next_hour(cursor)))
+}
+
+/// The next local hour boundary after `at`.
+fn next_hour(at: DateTime<Utc>) -> DateTime<Utc> {
+    let local = at.with_timezone(&Local);
+    let into_hour = Duration::seconds((local.minute() * 60 + local.second()) as i64)
+        + Duration::nanoseconds(local.nanosecond() as i64);
+    at - into_hour + Duration::hours(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{local, sample};
+
+    /// Hourly session samples over a week from Monday, using 10% an hour from 18:00 to
+    /// 22:00 only.
+    fn evening_user() -> Vec<Sample> {
+        let mut samples = Vec::new();
+        for day in 5..12 {
+            let mut used = 0.0;
+            for hour in 8..23 {
+                if (19..=22).contains(&hour) {
+                    used += 10.0;
+                }
+                samples.push(sample(local(day, hour), BucketKind::Session, used, local(day + 1, 0)));
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn learns_when_usage_happens() {
+        let profile = UsageProfile::learn(&evening_user()).unwrap();
+        assert_eq!(profile.weights.len(), HOURS_PER_WEEK);
+        assert!((profile.weights.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(profile.observed_hours >= 90.0);
+
+        // Wednesday 19:00 vs Wednesday 10:00
+        let evening = profile.weights[2 * 24 + 19];
+        let morning = profile.weights[2 * 24 + 10];
+        assert!(evening > 5.0 * morning, "evening {} morning {}", evening, morning);
+
+        assert_eq!(UsageProfile::learn(&evening_user()[..20]), None);
+    }
+
+    #[test]
+    fn weighted_time_follows_the_profile() {
+        let profile = UsageProfile::learn(&evening_user()).unwrap();
+        let morning = profile.weighted_seconds_between(local(14, 9), local(14, 12));
+        let evening = profile.weighted_seconds_between(local(14, 18), local(14, 21));
+        assert!(evening > 5.0 * morning);
+
+        // Advancing by a span's weighted length lands at its end
+        let at = profile.advance_weighted(local(14, 9), morning + evening, 48).unwrap();
+        let expected = profile.weighted_seconds_between(local(14, 9), at);
+        assert!((expected - (morning + evening)).abs() < 2.0 * profile.scale(hour_of_week(at)));
+        assert!(at >= local(14, 19) && at <= local(14, 21));
+    }
+}
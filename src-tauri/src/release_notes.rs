@@ -0,0 +1,119 @@
+//! Changelogs of offered updates, cached per version so notes of skipped releases stay readable.
+
+use pulldown_cmark::{html, Event, Options, Parser};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReleaseNote {
+    pub version: String,
+    /// Release body as written on GitHub (Markdown).
+    pub markdown: String,
+}
+
+/// A release note ready for the update window.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RenderedNote {
+    pub version: String,
+    pub html: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NotesCache {
+    notes: Vec<ReleaseNote>,
+}
+
+impl NotesCache {
+    /// Add or replace the note for `note.version` (release bodies get edited after publishing).
+    pub fn insert(&mut self, note: ReleaseNote) {
+        match self.notes.iter_mut().find(|n| n.version == note.version) {
+            Some(existing) => *existing = note,
+            None => self.notes.push(note),
+        }
+    }
+
+    /// Drop notes for versions we're already running (or older), and unparseable ones.
+    pub fn prune(&mut self, current: &Version) {
+        self.notes
+            .retain(|n| Version::parse(&n.version).is_ok_and(|v| v > *current));
+    }
+
+    /// Notes for everything after `current` up to and including `offered`, newest first.
+    pub fn between(&self, current: &Version, offered: &Version) -> Vec<ReleaseNote> {
+        let mut notes: Vec<(Version, &ReleaseNote)> = self
+            .notes
+            .iter()
+            .filter_map(|n| Version::parse(&n.version).ok().map(|v| (v, n)))
+            .filter(|(v, _)| v > current && v <= offered)
+            .collect();
+        notes.sort_by(|(a, _), (b, _)| b.cmp(a));
+        notes.into_iter().map(|(_, n)| n.clone()).collect()
+    }
+}
+
+/// Render release Markdown to HTML. Raw HTML in the body is shown as text, not injected.
+pub fn render_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let parser = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        other => other,
+    });
+    let mut out = String::new();
+    html::push_html(&mut out, parser);
+    out
+}
+
+pub fn render(notes: &[ReleaseNote]) -> Vec<RenderedNote> {
+    notes
+        .iter()
+        .map(|n| RenderedNote {
+            version: n.version.clone(),
+            html: render_html(&n.markdown),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(version: &str) -> ReleaseNote {
+        ReleaseNote {
+            version: version.to_string(),
+            markdown: format!("Changes in {}", version),
+        }
+    }
+
+    #[test]
+    fn notes_span_skipped_versions_newest_first() {
+        let mut cache = NotesCache::default();
+        for v in ["0.6.0", "0.7.0", "0.7.1-beta.1", "0.7.1", "0.8.0"] {
+            cache.insert(note(v));
+        }
+        cache.insert(ReleaseNote {
+            version: "0.7.0".to_string(),
+            markdown: "Edited".to_string(),
+        });
+
+        let current = Version::parse("0.6.0").unwrap();
+        cache.prune(&current);
+        let versions: Vec<String> = cache
+            .between(&current, &Version::parse("0.7.1").unwrap())
+            .into_iter()
+            .map(|n| n.version)
+            .collect();
+        assert_eq!(versions, ["0.7.1", "0.7.1-beta.1", "0.7.0"]);
+        assert_eq!(cache.between(&current, &current), []);
+        assert_eq!(cache.notes.len(), 4);
+        assert!(cache.notes.iter().any(|n| n.markdown == "Edited"));
+    }
+
+    #[test]
+    fn renders_markdown_and_escapes_raw_html() {
+        let html = render_html("## Fixes\n\n- **Faster** polling\n\n<script>alert(1)</script>\n");
+        assert!(html.contains("<h2>Fixes</h2>"));
+        assert!(html.contains("<strong>Faster</strong>"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;"));
+    }
+}
@@ -0,0 +1,309 @@
+//! Weekly and monthly usage summaries from stored history, as Markdown or HTML.
+
+use crate::history::{self, Sample};
+use crate::profile::UsageProfile;
+use crate::release_notes;
+use crate::usage::{online_seconds_between, BucketKind};
+use chrono::{DateTime, Duration, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// How many of the busiest hours of the week to list.
+const BUSIEST_HOURS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ReportPeriod {
+    #[default]
+    Week,
+    /// The last four weeks, which is all the history that's kept.
+    Month,
+}
+
+impl ReportPeriod {
+    fn days(self) -> i64 {
+        match self {
+            ReportPeriod::Week => 7,
+            ReportPeriod::Month => 28,
+        }
+    }
+
+    fn adjective(self) -> &'static str {
+        match self {
+            ReportPeriod::Week => "weekly",
+            ReportPeriod::Month => "monthly",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "md",
+            ReportFormat::Html => "html",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BucketSummary {
+    pub kind: BucketKind,
+    /// Windows with at least one sample in the period.
+    pub windows: usize,
+    /// Mean of all samples.
+    pub average_utilization: f64,
+    /// Mean of each window's highest sample.
+    pub average_peak: f64,
+    pub highest_peak: f64,
+    /// Windows that reached 100%.
+    pub limited_windows: usize,
+    /// Online hours between reaching 100% and the window's reset, like the popup's gap.
+    pub limited_hours: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BusyHour {
+    /// E.g. "Wed 7 PM".
+    pub label: String,
+    /// Share of a typical week's usage in this hour.
+    pub share: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub period: ReportPeriod,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Most-used bucket (by average peak) first; buckets without samples are left out.
+    pub buckets: Vec<BucketSummary>,
+    /// Empty until there's enough history to learn a usage profile from.
+    pub busiest_hours: Vec<BusyHour>,
+}
+
+impl Report {
+    pub fn generate(samples: &[Sample], period: ReportPeriod, now: DateTime<Utc>) -> Self {
+        let from = now - Duration::days(period.days());
+        let in_period: Vec<Sample> = samples
+            .iter()
+            .filter(|s| s.at >= from && s.at <= now)
+            .cloned()
+            .collect();
+
+        let mut buckets: Vec<BucketSummary> = [BucketKind::Session, BucketKind::Weekly]
+            .into_iter()
+            .filter_map(|kind| summarize(kind, &in_period, now))
+            .collect();
+        buckets.sort_by(|a, b| b.average_peak.total_cmp(&a.average_peak));
+
+        let busiest_hours = UsageProfile::learn(&in_period)
+            .map(|profile| {
+                let mut bins: Vec<(usize, f64)> = profile.weights.iter().copied().enumerate().collect();
+                bins.sort_by(|a, b| b.1.total_cmp(&a.1));
+                bins.into_iter()
+                    .take(BUSIEST_HOURS)
+                    .map(|(bin, share)| BusyHour {
+                        label: hour_of_week_label(bin),
+                        share,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            period,
+            from,
+            to: now,
+            buckets,
+            busiest_hours,
+        }
+    }
+
+    pub fn markdown(&self) -> String {
+        let date = |at: DateTime<Utc>| at.with_timezone(&Local).format("%b %-d, %Y").to_string();
+        let mut out = format!(
+            "# TokenTorch {} report\n\n{} – {}\n\n",
+            self.period.adjective(),
+            date(self.from),
+            date(self.to)
+        );
+        if self.buckets.is_empty() {
+            out.push_str("No usage was recorded in this period.\n");
+            return out;
+        }
+
+        out.push_str("| Bucket | Windows | Avg. usage | Avg. peak | Highest peak | Hit the limit | Rate-limited |\n");
+        out.push_str("|---|---:|---:|---:|---:|---:|---:|\n");
+        for b in &self.buckets {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {:.0}% | {:.0}% | {:.0}% | {} | {} |",
+                b.kind.label(),
+                b.windows,
+                b.average_utilization,
+                b.average_peak,
+                b.highest_peak,
+                b.limited_windows,
+                format_hours(b.limited_hours)
+            );
+        }
+
+        if !self.busiest_hours.is_empty() {
+            out.push_str("\n## Busiest hours\n\n");
+            for hour in &self.busiest_hours {
+                let _ = writeln!(out, "- {} — {:.1}% of a typical week's usage", hour.label, hour.share * 100.0);
+            }
+        }
+        out
+    }
+
+    /// The report as an HTML fragment, for the report window.
+    pub fn html(&self) -> String {
+        release_notes::render_html(&self.markdown())
+    }
+
+    /// A standalone HTML page, for export.
+    pub fn html_document(&self) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\" />\n\
+             <title>TokenTorch {} report</title>\n<style>\n\
+             body {{ font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif; margin: 32px; }}\n\
+             table {{ border-collapse: collapse; }}\n\
+             th, td {{ border: 1px solid #ccc; padding: 4px 10px; }}\n\
+             </style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            self.period.adjective(),
+            self.html()
+        )
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Markdown => self.markdown(),
+            ReportFormat::Html => self.html_document(),
+        }
+    }
+
+    /// One line for the weekly notification, e.g. "Session: avg. peak 61%, limit hit in 2 windows".
+    pub fn summary_line(&self) -> String {
+        if self.buckets.is_empty() {
+            return "No usage recorded".to_string();
+        }
+        self.buckets
+            .iter()
+            .map(|b| {
+                let mut line = format!("{}: avg. peak {:.0}%", b.kind.label(), b.average_peak);
+                match b.limited_windows {
+                    0 => {}
+                    1 => line.push_str(", limit hit once"),
+                    n => line.push_str(&format!(", limit hit in {} windows", n)),
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join(" · ")
+    }
+}
+
+fn summarize(kind: BucketKind, samples: &[Sample], now: DateTime<Utc>) -> Option<BucketSummary> {
+    let of_kind: Vec<&Sample> = samples.iter().filter(|s| s.kind == kind).collect();
+    if of_kind.is_empty() {
+        return None;
+    }
+    let windows = history::group_windows(samples, kind);
+
+    let peaks: Vec<f64> = windows
+        .iter()
+        .map(|w| w.iter().map(|s| s.utilization).fold(0.0, f64::max))
+        .collect();
+    let limited_seconds: f64 = windows
+        .iter()
+        .filter_map(|w| {
+            let hit = w.iter().find(|s| s.utilization >= 100.0)?;
+            let reset = hit.resets_at.parse::<DateTime<Utc>>().ok()?;
+            Some(online_seconds_between(hit.at, reset.min(now)))
+        })
+        .sum();
+
+    Some(BucketSummary {
+        kind,
+        windows: windows.len(),
+        average_utilization: of_kind.iter().map(|s| s.utilization).sum::<f64>() / of_kind.len() as f64,
+        average_peak: peaks.iter().sum::<f64>() / peaks.len() as f64,
+        highest_peak: peaks.iter().copied().fold(0.0, f64::max),
+        limited_windows: peaks.iter().filter(|p| **p >= 100.0).count(),
+        limited_hours: limited_seconds / 3600.0,
+    })
+}
+
+fn hour_of_week_label(bin: usize) -> String {
+    const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    let hour = bin % 24;
+    let (twelve, meridiem) = match hour {
+        0 => (12, "AM"),
+        1..=11 => (hour, "AM"),
+        12 => (12, "PM"),
+        _ => (hour - 12, "PM"),
+    };
+    format!("{} {} {}", DAYS[bin / 24], twelve, meridiem)
+}
+
+fn format_hours(hours: f64) -> String {
+    let minutes = (hours * 60.0).round() as u64;
+    match minutes {
+        0 => "—".to_string(),
+        m if m < 60 => format!("{}m", m),
+        m => format!("{}h {}m", m / 60, m % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{local, sample};
+
+    #[test]
+    fn summarizes_windows_and_time_at_the_limit() {
+        let session = BucketKind::Session;
+        let samples = vec![
+            // Thursday: a window that peaks at 60%
+            sample(local(15, 9), session, 20.0, local(15, 14)),
+            sample(local(15, 11), session, 60.0, local(15, 14)),
+            // Friday: hits the limit at 11:00, resets at 13:00
+            sample(local(16, 9), session, 50.0, local(16, 13)),
+            sample(local(16, 11), session, 100.0, local(16, 13)),
+            sample(local(16, 12), session, 100.0, local(16, 13)),
+            sample(local(16, 9), BucketKind::Weekly, 30.0, local(20, 9)),
+            // Before the period
+            sample(local(2, 9), session, 100.0, local(2, 13)),
+        ];
+
+        let report = Report::generate(&samples, ReportPeriod::Week, local(18, 12));
+        let summary = &report.buckets[0];
+        assert_eq!(summary.kind, BucketKind::Session);
+        assert_eq!(summary.windows, 2);
+        assert_eq!(summary.average_peak, 80.0);
+        assert_eq!(summary.highest_peak, 100.0);
+        assert_eq!(summary.limited_windows, 1);
+        assert!((summary.limited_hours - 2.0).abs() < 1e-9);
+        assert_eq!(report.buckets[1].kind, BucketKind::Weekly);
+        assert!(report.busiest_hours.is_empty());
+
+        let markdown = report.markdown();
+        assert!(markdown.starts_with("# TokenTorch weekly report\n\nJan 11, 2026 – Jan 18, 2026"));
+        assert!(markdown.contains("| Session | 2 | 66% | 80% | 100% | 1 | 2h 0m |"));
+        assert!(report.html().contains("<td>Session</td>"));
+        assert_eq!(report.summary_line(), "Session: avg. peak 80%, limit hit once · Weekly: avg. peak 30%");
+    }
+
+    #[test]
+    fn empty_period_says_so() {
+        let report = Report::generate(&[], ReportPeriod::Month, local(18, 12));
+        assert!(report.markdown().ends_with("No usage was recorded in this period.\n"));
+        assert_eq!(hour_of_week_label(2 * 24 + 19), "Wed 7 PM");
+        assert_eq!(hour_of_week_label(6 * 24), "Sun 12 AM");
+    }
+}
@@ -0,0 +1,98 @@
+//! OS signals that affect how loudly we alert: reduced-motion preference and user idle time.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long the reduced-motion setting is cached before asking the OS again.
+const REDUCED_MOTION_CACHE: Duration = Duration::from_secs(60);
+
+static REDUCED_MOTION: Mutex<Option<(Instant, bool)>> = Mutex::new(None);
+
+/// Whether the OS asks apps to minimize animation. Cached for a minute.
+pub fn prefers_reduced_motion() -> bool {
+    let mut cached = REDUCED_MOTION.lock().unwrap();
+    if let Some((checked_at, value)) = *cached {
+        if checked_at.elapsed() < REDUCED_MOTION_CACHE {
+            return value;
+        }
+    }
+    let value = query_reduced_motion();
+    *cached = Some((Instant::now(), value));
+    value
+}
+
+/// Seconds since the last keyboard/mouse input, or None if unknown on this platform.
+/// A locked screen shows up as a growing idle time.
+pub fn user_idle_seconds() -> Option<f64> {
+    query_idle_seconds()
+}
+
+#[cfg(target_os = "macos")]
+fn query_reduced_motion() -> bool {
+    std::process::Command::new("defaults")
+        .args(["read", "com.apple.universalaccess", "reduceMotion"])
+        .output()
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim() == "1")
+        .unwrap_or(false)
+}
+
+#[cfg(target_os = "windows")]
+fn query_reduced_motion() -> bool {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        SystemParametersInfoW, SPI_GETCLIENTAREAANIMATION,
+    };
+
+    let mut animations_enabled: i32 = 1;
+    let ok = unsafe {
+        SystemParametersInfoW(
+            SPI_GETCLIENTAREAANIMATION,
+            0,
+            &mut animations_enabled as *mut i32 as *mut core::ffi::c_void,
+            0,
+        )
+    };
+    ok != 0 && animations_enabled == 0
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn query_reduced_motion() -> bool {
+    false
+}
+
+#[cfg(target_os = "macos")]
+fn query_idle_seconds() -> Option<f64> {
+    // kCGEventSourceStateCombinedSessionState, kCGAnyInputEventType
+    const COMBINED_SESSION_STATE: i32 = 0;
+    const ANY_INPUT_EVENT: u32 = !0;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventSourceSecondsSinceLastEventType(source_state: i32, event_type: u32) -> f64;
+    }
+
+    let secs = unsafe {
+        CGEventSourceSecondsSinceLastEventType(COMBINED_SESSION_STATE, ANY_INPUT_EVENT)
+    };
+    secs.is_finite().then_some(secs.max(0.0))
+}
+
+#[cfg(target_os = "windows")]
+fn query_idle_seconds() -> Option<f64> {
+    use windows_sys::Win32::System::SystemInformation::GetTickCount;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+    let mut info = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    if unsafe { GetLastInputInfo(&mut info) } == 0 {
+        return None;
+    }
+    let idle_ms = unsafe { GetTickCount() }.wrapping_sub(info.dwTime);
+    Some(idle_ms as f64 / 1000.0)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn query_idle_seconds() -> Option<f64> {
+    None
+}
@@ -0,0 +1,83 @@
+//! Builders for the usage values unit tests keep needing: local times, API responses,
+//! history samples, bars and webhook events.
+
+use crate::history::Sample;
+use crate::usage::{ApiUsageResponse, BucketKind, UsageBar, UsageBucket, UsageColor};
+use crate::webhook::{Trigger, WebhookEvent};
+use chrono::{DateTime, Local, TimeZone, Utc};
+
+/// Reset time used by `bar` and `event`.
+pub const RESETS_AT: &str = "2026-01-16T09:00:00Z";
+/// Org ID `sample` records under.
+pub const ORG_ID: &str = "org";
+
+/// `hour`:00 local time on `day` January 2026. The 5th is a Monday.
+pub fn local(day: u32, hour: u32) -> DateTime<Utc> {
+    Local
+        .with_ymd_and_hms(2026, 1, day, hour, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+/// A usage response with only the session bucket.
+pub fn response(session: f64, resets_at: &str) -> ApiUsageResponse {
+    let mut response = ApiUsageResponse::default();
+    response.buckets.insert(
+        "five_hour".to_string(),
+        UsageBucket {
+            utilization: session,
+            resets_at: resets_at.to_string(),
+        },
+    );
+    response
+}
+
+pub fn sample(at: DateTime<Utc>, kind: BucketKind, utilization: f64, resets_at: DateTime<Utc>) -> Sample {
+    Sample {
+        at,
+        org_id: ORG_ID.to_string(),
+        kind,
+        utilization,
+        resets_at: resets_at.to_rfc3339(),
+    }
+}
+
+/// A bar at 90% projected to 120%, resetting at `RESETS_AT`; override fields with
+/// struct update syntax.
+pub fn bar(kind: BucketKind, color: UsageColor) -> UsageBar {
+    UsageBar {
+        kind,
+        label: kind.label().to_string(),
+        utilization: 90.0,
+        resets_at: RESETS_AT.to_string(),
+        seconds_remaining: 3600.0,
+        projected: 120.0,
+        projected_low: 120.0,
+        projected_high: 120.0,
+        color,
+        reset_display: String::new(),
+        gap_display: None,
+        limit_at: None,
+        limit_display: None,
+        current_rate: None,
+        safe_rate: None,
+        pace_display: None,
+        stats_display: None,
+        acknowledged: false,
+    }
+}
+
+/// A red weekly bar of the "Alice" profile.
+pub fn event(trigger: Trigger) -> WebhookEvent {
+    WebhookEvent {
+        trigger,
+        profile: "Alice".to_string(),
+        kind: BucketKind::Weekly,
+        label: "Weekly".to_string(),
+        color: UsageColor::Red,
+        utilization: 64.4,
+        projected: 131.0,
+        resets_at: RESETS_AT.to_string(),
+        reset_display: "resets Fri 10:00 AM".to_string(),
+    }
+}
@@ -0,0 +1,85 @@
+use crate::usage::UsageColor;
+use serde::{Deserialize, Serialize};
+
+pub type Rgba = (u8, u8, u8, u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ColorTheme {
+    #[default]
+    Default,
+    /// Okabe-Ito sky blue / yellow / vermillion — distinguishable with red-green color blindness.
+    ColorblindSafe,
+    HighContrast,
+    /// Single-color icon. On macOS this is a template image the OS tints for dark/light menu bars.
+    Monochrome,
+}
+
+impl ColorTheme {
+    pub fn fill(self, color: UsageColor) -> Rgba {
+        let (r, g, b) = match (self, color) {
+            (_, UsageColor::Gray) => (120, 120, 120),
+
+            (ColorTheme::Default, UsageColor::Green) => (76, 175, 80),
+            (ColorTheme::Default, UsageColor::Yellow) => (255, 152, 0),
+            (ColorTheme::Default, UsageColor::Red | UsageColor::RedBlink) => (198, 40, 40),
+
+            (ColorTheme::ColorblindSafe, UsageColor::Green) => (86, 180, 233),
+            (ColorTheme::ColorblindSafe, UsageColor::Yellow) => (240, 228, 66),
+            (ColorTheme::ColorblindSafe, UsageColor::Red | UsageColor::RedBlink) => (213, 94, 0),
+
+            (ColorTheme::HighContrast, UsageColor::Green) => (0, 230, 118),
+            (ColorTheme::HighContrast, UsageColor::Yellow) => (255, 234, 0),
+            (ColorTheme::HighContrast, UsageColor::Red | UsageColor::RedBlink) => (255, 23, 68),
+
+            // Template images only use alpha; elsewhere draw light bars for dark taskbars
+            (ColorTheme::Monochrome, _) if cfg!(target_os = "macos") => (0, 0, 0),
+            (ColorTheme::Monochrome, _) => (235, 235, 235),
+        };
+        (r, g, b, 255)
+    }
+
+    pub fn track(self) -> Rgba {
+        match self {
+            ColorTheme::Default | ColorTheme::ColorblindSafe => (68, 68, 72, 255),
+            ColorTheme::HighContrast => (30, 30, 30, 255),
+            ColorTheme::Monochrome if cfg!(target_os = "macos") => (0, 0, 0, 80),
+            ColorTheme::Monochrome => (235, 235, 235, 80),
+        }
+    }
+
+    /// Whether the tray icon should be flagged as a macOS template image.
+    pub fn is_template(self) -> bool {
+        self == ColorTheme::Monochrome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn luminance((r, g, b, _): Rgba) -> f64 {
+        0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64
+    }
+
+    #[test]
+    fn colored_themes_distinguish_severity_by_luminance() {
+        for theme in [
+            ColorTheme::Default,
+            ColorTheme::ColorblindSafe,
+            ColorTheme::HighContrast,
+        ] {
+            let green = luminance(theme.fill(UsageColor::Green));
+            let yellow = luminance(theme.fill(UsageColor::Yellow));
+            let red = luminance(theme.fill(UsageColor::Red));
+            assert!((green - red).abs() > 20.0, "{theme:?}: green vs red");
+            assert!((yellow - red).abs() > 20.0, "{theme:?}: yellow vs red");
+        }
+    }
+
+    #[test]
+    fn only_monochrome_is_template() {
+        assert!(ColorTheme::Monochrome.is_template());
+        assert!(!ColorTheme::Default.is_template());
+        assert!(!ColorTheme::ColorblindSafe.is_template());
+    }
+}
@@ -1,88 +1,1032 @@
-use serde::Deserialize;
+use crate::config::{UpdateChannel, UpdateSettings};
+use crate::net::NetworkConfig;
+use crate::release_notes::{self, NotesCache, ReleaseNote, RenderedNote};
+use minisign_verify::{PublicKey, Signature};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
 
 const GITHUB_REPO: &str = "TekSiDoT/tokentorch";
+const GITHUB_API: &str = "https://api.github.com";
 const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+const USER_AGENT: &str = "tokentorch";
+
+/// Minisign public key (the base64 line of the `.pub` file) release assets must be signed with.
+/// Baked in at build time; without it we only point the user at the release page.
+const UPDATE_PUBKEY: Option<&str> = option_env!("TOKENTORCH_UPDATE_PUBKEY");
+
+/// Same identifier as tauri.conf.json, so updates live next to the rest of the app data.
+pub(crate) const APP_IDENTIFIER: &str = "com.tokentorch.app";
+
+/// Launches a freshly installed version gets to confirm it started before we roll back.
+const START_ATTEMPTS: u32 = 1;
 
 #[derive(Debug, Clone)]
 pub struct UpdateInfo {
     pub version: String,
     pub url: String,
+    /// Signed installable for this platform, if the release has one.
+    pub asset: Option<UpdateAsset>,
+    /// Changelogs from the running version up to this one, newest first.
+    pub notes: Vec<ReleaseNote>,
+}
+
+impl UpdateInfo {
+    /// Whether this update can be downloaded and installed in-app.
+    pub fn can_self_update(&self) -> bool {
+        UPDATE_PUBKEY.is_some() && self.asset.is_some()
+    }
+
+    pub fn details(&self, progress: UpdateProgress) -> UpdateDetails {
+        UpdateDetails {
+            version: self.version.clone(),
+            current_version: CURRENT_VERSION.to_string(),
+            url: self.url.clone(),
+            can_self_update: self.can_self_update(),
+            progress,
+            notes: release_notes::render(&self.notes),
+        }
+    }
+}
+
+/// What the update window shows.
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateDetails {
+    pub version: String,
+    pub current_version: String,
+    pub url: String,
+    pub can_self_update: bool,
+    pub progress: UpdateProgress,
+    pub notes: Vec<RenderedNote>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateAsset {
+    pub name: String,
+    pub url: String,
+    pub signature_url: String,
+}
+
+/// In-app update progress, shown in the tray menu and update window.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub enum UpdateProgress {
+    #[default]
+    Idle,
+    Downloading,
+    /// Verified and staged; installed on the next start.
+    ReadyToRestart,
+}
+
+/// Where releases are listed.
+enum Feed<'a> {
+    /// GitHub REST API base URL.
+    GitHub(&'a str),
+    /// Static manifest URL: `{ "releases": [ { "version", "notes", "url", "prerelease", "assets": [ { "name", "url" } ] } ] }`.
+    /// Asset URLs may be relative to the manifest.
+    Manifest(&'a str),
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    releases: Vec<ManifestRelease>,
+}
+
+/// A manifest entry; links may be relative to the manifest.
+#[derive(Deserialize)]
+struct ManifestRelease {
+    version: String,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    assets: Vec<ManifestAsset>,
 }
 
 #[derive(Deserialize)]
-struct GitHubRelease {
+struct ManifestAsset {
+    name: String,
+    url: String,
+}
+
+impl ManifestRelease {
+    fn into_release(self, base: &reqwest::Url) -> Release {
+        let resolve = |link: String| match base.join(&link) {
+            Ok(absolute) => absolute.to_string(),
+            Err(_) => link,
+        };
+        Release {
+            tag_name: self.version,
+            html_url: if self.url.is_empty() { self.url } else { resolve(self.url) },
+            draft: false,
+            prerelease: self.prerelease,
+            body: self.notes,
+            assets: self
+                .assets
+                .into_iter()
+                .map(|a| ReleaseAsset {
+                    name: a.name,
+                    browser_download_url: resolve(a.url),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A GitHub release.
+#[derive(Deserialize)]
+struct Release {
     tag_name: String,
+    #[serde(default)]
     html_url: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    assets: Vec<ReleaseAsset>,
+}
+
+impl Release {
+    fn version(&self) -> Option<Version> {
+        parse_version(&self.tag_name)
+    }
+
+    /// Stable ignores anything marked prerelease or carrying a prerelease version.
+    fn on_channel(&self, version: &Version, channel: UpdateChannel) -> bool {
+        !self.draft
+            && match channel {
+                UpdateChannel::Stable => !self.prerelease && version.pre.is_empty(),
+                UpdateChannel::Beta => true,
+            }
+    }
+}
+
+#[derive(Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// A downloaded, signature-checked update waiting for the next start.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StagedUpdate {
+    pub version: String,
+    pub file: PathBuf,
+}
+
+/// An installed update that hasn't confirmed a successful start yet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PendingUpdate {
+    version: String,
+    /// What was replaced (app bundle or executable) and where the previous copy was kept.
+    target: PathBuf,
+    backup: PathBuf,
+    attempts: u32,
+}
+
+#[derive(Debug, PartialEq)]
+enum StartupAction {
+    /// Keep starting; confirm once setup completes.
+    Continue,
+    /// The new version keeps failing to start — put the previous one back.
+    RollBack,
+    /// We aren't the version that was installed (installer failed, manual reinstall).
+    Discard,
+}
+
+/// Parse a release tag or version string, with or without a leading "v".
+fn parse_version(tag: &str) -> Option<Version> {
+    Version::parse(tag.trim().strip_prefix('v').unwrap_or(tag.trim())).ok()
+}
+
+/// Releases on `channel` newer than `current`, with their parsed versions.
+fn newer_releases<'a>(
+    releases: &'a [Release],
+    channel: UpdateChannel,
+    current: &'a Version,
+) -> impl Iterator<Item = (Version, &'a Release)> + 'a {
+    releases
+        .iter()
+        .filter_map(|r| r.version().map(|v| (v, r)))
+        .filter(move |(v, r)| r.on_channel(v, channel) && v > current)
 }
 
-/// Compare two semver-style version strings (e.g. "0.3.1" vs "0.4.0").
-/// Returns true if `remote` is newer than `local`.
-fn is_newer(remote: &str, local: &str) -> bool {
-    let parse = |v: &str| -> Vec<u64> {
-        v.split('.')
-            .filter_map(|s| s.parse::<u64>().ok())
-            .collect()
+/// Newest release on `channel` that is newer than `current` and not in `skipped`.
+/// Versions compare by semver precedence, so `0.7.0-beta.1` < `0.7.0` < `0.7.1`.
+fn select_release<'a>(
+    releases: &'a [Release],
+    channel: UpdateChannel,
+    current: &'a Version,
+    skipped: &[Version],
+) -> Option<(Version, &'a Release)> {
+    newer_releases(releases, channel, current)
+        .filter(|(v, _)| !skipped.contains(v))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+}
+
+/// Release asset suffix we know how to install on this platform.
+fn platform_asset_suffix() -> Option<&'static str> {
+    if cfg!(target_os = "macos") {
+        Some(".app.tar.gz")
+    } else if cfg!(target_os = "windows") {
+        Some("-setup.exe")
+    } else {
+        None
+    }
+}
+
+/// Pick the installable for this platform; only assets with a `.minisig` next to them qualify.
+fn pick_asset(assets: &[ReleaseAsset], suffix: &str) -> Option<UpdateAsset> {
+    let asset = assets.iter().find(|a| a.name.ends_with(suffix))?;
+    let sig_name = format!("{}.minisig", asset.name);
+    let sig = assets.iter().find(|a| a.name == sig_name)?;
+    Some(UpdateAsset {
+        name: asset.name.clone(),
+        url: asset.browser_download_url.clone(),
+        signature_url: sig.browser_download_url.clone(),
+    })
+}
+
+/// Check the release feed (GitHub unless configured otherwise) for a newer version on the
+/// configured channel. Returns None on any error, and when update checks are disabled.
+pub async fn check_for_update(
+    settings: &UpdateSettings,
+    network: &NetworkConfig,
+) -> Option<UpdateInfo> {
+    if !settings.enabled {
+        return None;
+    }
+    let feed = match settings.feed_url.as_deref() {
+        Some(url) => Feed::Manifest(url),
+        None => Feed::GitHub(GITHUB_API),
     };
-    let r = parse(remote);
-    let l = parse(local);
-    // Compare component by component
-    for i in 0..r.len().max(l.len()) {
-        let rv = r.get(i).copied().unwrap_or(0);
-        let lv = l.get(i).copied().unwrap_or(0);
-        if rv > lv {
-            return true;
+
+    let mut skipped: Vec<Version> = settings
+        .skipped_version
+        .as_deref()
+        .and_then(parse_version)
+        .into_iter()
+        .collect();
+    // Versions that already failed to start here once
+    if let Some(rejected) = update_dir()
+        .and_then(|dir| fs::read_to_string(dir.join("rejected")).ok())
+        .and_then(|v| parse_version(&v))
+    {
+        skipped.push(rejected);
+    }
+
+    let mut info = fetch_update(
+        &http_client(network),
+        &feed,
+        settings.channel,
+        &skipped,
+        platform_asset_suffix(),
+    )
+    .await?;
+
+    // Merge into the notes cache so changelogs of skipped versions stay readable
+    if let (Some(dir), Some(current), Some(offered)) = (
+        update_dir(),
+        parse_version(CURRENT_VERSION),
+        parse_version(&info.version),
+    ) {
+        let path = dir.join("notes.json");
+        let mut cache: NotesCache = read_json(&path).unwrap_or_default();
+        for note in info.notes.drain(..) {
+            cache.insert(note);
         }
-        if rv < lv {
-            return false;
+        cache.prune(&current);
+        if fs::create_dir_all(&dir).is_ok() {
+            if let Err(e) = write_json(&path, &cache) {
+                eprintln!("[updater] {}", e);
+            }
         }
+        info.notes = cache.between(&current, &offered);
     }
-    false
+    Some(info)
 }
 
-/// Check GitHub Releases for a newer version. Returns None on any error.
-pub async fn check_for_update() -> Option<UpdateInfo> {
-    let url = format!(
-        "https://api.github.com/repos/{}/releases/latest",
-        GITHUB_REPO
-    );
+async fn fetch_releases(client: &reqwest::Client, feed: &Feed<'_>) -> Option<Vec<Release>> {
+    let url = match feed {
+        // `/releases/latest` never returns prereleases, so list recent releases instead
+        Feed::GitHub(api_base) => format!("{}/repos/{}/releases?per_page=30", api_base, GITHUB_REPO),
+        Feed::Manifest(url) => url.to_string(),
+    };
 
-    let client = reqwest::Client::new();
-    let resp = client
-        .get(&url)
-        .header("User-Agent", "tokentorch")
-        .send()
-        .await
-        .ok()?;
+    let resp = client.get(&url).send().await.ok()?;
 
     if !resp.status().is_success() {
         return None;
     }
 
-    let release: GitHubRelease = resp.json().await.ok()?;
-    let remote_version = release.tag_name.strip_prefix('v').unwrap_or(&release.tag_name);
+    match feed {
+        Feed::GitHub(_) => resp.json().await.ok(),
+        Feed::Manifest(url) => {
+            let manifest = resp.json::<Manifest>().await.ok()?;
+            let base = reqwest::Url::parse(url).ok()?;
+            Some(manifest.releases.into_iter().map(|r| r.into_release(&base)).collect())
+        }
+    }
+}
 
-    if is_newer(remote_version, CURRENT_VERSION) {
-        Some(UpdateInfo {
-            version: remote_version.to_string(),
-            url: release.html_url,
+async fn fetch_update(
+    client: &reqwest::Client,
+    feed: &Feed<'_>,
+    channel: UpdateChannel,
+    skipped: &[Version],
+    asset_suffix: Option<&str>,
+) -> Option<UpdateInfo> {
+    let releases = fetch_releases(client, feed).await?;
+    let current = parse_version(CURRENT_VERSION)?;
+    let (version, release) = select_release(&releases, channel, &current, skipped)?;
+    let mut notes: Vec<(Version, ReleaseNote)> = newer_releases(&releases, channel, &current)
+        .filter(|(v, _)| *v <= version)
+        .filter_map(|(v, r)| {
+            let body = r.body.as_deref()?.trim();
+            let note = ReleaseNote {
+                version: v.to_string(),
+                markdown: body.to_string(),
+            };
+            (!body.is_empty()).then_some((v, note))
         })
+        .collect();
+    notes.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    Some(UpdateInfo {
+        version: version.to_string(),
+        url: release.html_url.clone(),
+        asset: asset_suffix.and_then(|suffix| pick_asset(&release.assets, suffix)),
+        notes: notes.into_iter().map(|(_, note)| note).collect(),
+    })
+}
+
+/// Check `data` against a minisign signature file's contents. The trusted comment must read
+/// `version:<version>\tfile:<file>`, so a signed asset can't be passed off as another release.
+pub fn verify_signature(
+    data: &[u8],
+    signature: &str,
+    public_key: &str,
+    version: &str,
+    file: &str,
+) -> Result<(), String> {
+    let key = PublicKey::from_base64(public_key.trim())
+        .map_err(|e| format!("Invalid update public key: {}", e))?;
+    let signature =
+        Signature::decode(signature).map_err(|e| format!("Invalid update signature: {}", e))?;
+    key.verify(data, &signature, false)
+        .map_err(|e| format!("Update signature does not match: {}", e))?;
+
+    let signed = |field: &str| {
+        signature
+            .trusted_comment()
+            .split('\t')
+            .find_map(|pair| pair.strip_prefix(field)?.strip_prefix(':'))
+    };
+    let signed_version = signed("version").and_then(parse_version);
+    if signed_version.is_none() || signed_version != parse_version(version) {
+        return Err(format!(
+            "Update signature is for version {}, not {}",
+            signed("version").unwrap_or("unknown"),
+            version
+        ));
+    }
+    if signed("file") != Some(file) {
+        return Err(format!(
+            "Update signature is for {}, not {}",
+            signed("file").unwrap_or("an unnamed file"),
+            file
+        ));
+    }
+    Ok(())
+}
+
+/// Where downloaded and pending updates are kept.
+pub fn update_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER).join("updates"))
+}
+
+/// Download the update's asset, verify it against the embedded key and stage it for the next start.
+pub async fn stage_update(
+    info: &UpdateInfo,
+    network: &NetworkConfig,
+) -> Result<StagedUpdate, String> {
+    let asset = info.asset.as_ref().ok_or("No installable asset for this platform")?;
+    let public_key = UPDATE_PUBKEY.ok_or("This build has no update signing key")?;
+    let dir = update_dir().ok_or("No app data directory")?;
+    download_and_stage(&http_client(network), &info.version, asset, public_key, &dir).await
+}
+
+fn http_client(network: &NetworkConfig) -> reqwest::Client {
+    network.build_client(reqwest::Client::builder(), USER_AGENT)
+}
+
+async fn download_and_stage(
+    client: &reqwest::Client,
+    version: &str,
+    asset: &UpdateAsset,
+    public_key: &str,
+    dir: &Path,
+) -> Result<StagedUpdate, String> {
+    let data = download(client, &asset.url).await?;
+    let signature = download(client, &asset.signature_url).await?;
+    let signature = String::from_utf8(signature)
+        .map_err(|_| "Update signature is not text".to_string())?;
+    verify_signature(&data, &signature, public_key, version, &asset.name)?;
+
+    let staged_dir = dir.join("staged");
+    let _ = fs::remove_dir_all(&staged_dir);
+    fs::create_dir_all(&staged_dir).map_err(|e| format!("Failed to create {:?}: {}", staged_dir, e))?;
+    let file = staged_dir.join(&asset.name);
+    fs::write(&file, &data).map_err(|e| format!("Failed to write {:?}: {}", file, e))?;
+
+    let staged = StagedUpdate {
+        version: version.to_string(),
+        file,
+    };
+    write_json(&dir.join("staged.json"), &staged)?;
+    Ok(staged)
+}
+
+async fn download(client: &reqwest::Client, url: &str) -> Result<Vec<u8>, String> {
+    let resp = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("Download failed: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("Download failed: HTTP {} for {}", resp.status(), url));
+    }
+    resp.bytes()
+        .await
+        .map(|body| body.to_vec())
+        .map_err(|e| format!("Download failed: {}", e))
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Option<T> {
+    let text = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    fs::write(path, text).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+/// Runs first thing on every start: installs a staged update (and relaunches), or rolls back
+/// an installed one that never confirmed a successful start. May exit the process.
+pub fn apply_staged_update() {
+    let Some(dir) = update_dir() else {
+        return;
+    };
+
+    let pending_path = dir.join("pending.json");
+    if let Some(mut pending) = read_json::<PendingUpdate>(&pending_path) {
+        match startup_action(&mut pending, CURRENT_VERSION) {
+            StartupAction::Continue => {
+                if let Err(e) = write_json(&pending_path, &pending) {
+                    eprintln!("[updater] {}", e);
+                }
+            }
+            StartupAction::RollBack => {
+                eprintln!("[updater] v{} failed to start, rolling back", pending.version);
+                let _ = fs::write(dir.join("rejected"), &pending.version);
+                let _ = fs::remove_file(&pending_path);
+                match roll_back(&pending.target, &pending.backup) {
+                    Ok(()) => relaunch(&pending.target),
+                    Err(e) => eprintln!("[updater] Rollback failed: {}", e),
+                }
+            }
+            StartupAction::Discard => {
+                let _ = remove_path(&pending.backup);
+                let _ = fs::remove_file(&pending_path);
+            }
+        }
+        return;
+    }
+
+    let staged_path = dir.join("staged.json");
+    let Some(staged) = read_json::<StagedUpdate>(&staged_path) else {
+        return;
+    };
+    // Consume the staged update first so a failing install can't loop
+    let _ = fs::remove_file(&staged_path);
+
+    match install(&staged, &dir) {
+        Ok(pending) => {
+            if let Err(e) = write_json(&pending_path, &pending) {
+                eprintln!("[updater] {}", e);
+            }
+            if cfg!(target_os = "windows") {
+                // The installer replaces the executable once we're gone, then relaunches it
+                std::process::exit(0);
+            }
+            relaunch(&pending.target);
+        }
+        Err(e) => eprintln!("[updater] Install of v{} failed: {}", staged.version, e),
+    }
+}
+
+/// Call once startup succeeded: the installed update is kept and its backup removed.
+pub fn confirm_startup() {
+    let Some(dir) = update_dir() else {
+        return;
+    };
+    let pending_path = dir.join("pending.json");
+    if let Some(pending) = read_json::<PendingUpdate>(&pending_path) {
+        if pending.version == CURRENT_VERSION {
+            let _ = remove_path(&pending.backup);
+            let _ = fs::remove_file(&pending_path);
+            let _ = fs::remove_dir_all(dir.join("staged"));
+        }
+    }
+}
+
+fn startup_action(pending: &mut PendingUpdate, current_version: &str) -> StartupAction {
+    if pending.version != current_version {
+        return StartupAction::Discard;
+    }
+    if pending.attempts >= START_ATTEMPTS {
+        return StartupAction::RollBack;
+    }
+    pending.attempts += 1;
+    StartupAction::Continue
+}
+
+/// macOS: swap the app bundle for the one in the archive, keeping the old bundle as backup.
+#[cfg(target_os = "macos")]
+fn install(staged: &StagedUpdate, dir: &Path) -> Result<PendingUpdate, String> {
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    let target = exe
+        .ancestors()
+        .find(|p| p.extension().is_some_and(|ext| ext == "app"))
+        .ok_or("Not running from an app bundle")?
+        .to_path_buf();
+
+    let extract_dir = dir.join("extract");
+    let _ = fs::remove_dir_all(&extract_dir);
+    fs::create_dir_all(&extract_dir).map_err(|e| e.to_string())?;
+    let status = std::process::Command::new("tar")
+        .arg("-xzf")
+        .arg(&staged.file)
+        .arg("-C")
+        .arg(&extract_dir)
+        .status()
+        .map_err(|e| format!("tar failed: {}", e))?;
+    if !status.success() {
+        return Err(format!("tar exited with {}", status));
+    }
+    let new_bundle = fs::read_dir(&extract_dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .find(|p| p.extension().is_some_and(|ext| ext == "app"))
+        .ok_or("Archive contains no app bundle")?;
+
+    let backup = dir.join("backup").join(target.file_name().unwrap_or_default());
+    swap_in(&new_bundle, &target, &backup)?;
+    let _ = fs::remove_dir_all(&extract_dir);
+
+    Ok(PendingUpdate {
+        version: staged.version.clone(),
+        target: exe,
+        backup,
+        attempts: 0,
+    })
+}
+
+/// Windows: keep a copy of the install directory and start the installer, which waits for
+/// us to exit before replacing the app. Best effort: a rollback restores the directory but
+/// not the installer's registry entries, so Windows keeps listing the newer version until
+/// the next update.
+#[cfg(target_os = "windows")]
+fn install(staged: &StagedUpdate, dir: &Path) -> Result<PendingUpdate, String> {
+    let target = std::env::current_exe().map_err(|e| e.to_string())?;
+    let install_dir = target.parent().ok_or("Executable has no directory")?;
+    let backup = dir.join("backup").join(install_dir.file_name().unwrap_or_default());
+    let _ = remove_path(&backup);
+    mirror_dir(install_dir, &backup).map_err(|e| format!("Failed to back up {:?}: {}", install_dir, e))?;
+
+    // NSIS installer: /P passive (progress only), /R relaunch the app when done
+    std::process::Command::new(&staged.file)
+        .args(["/P", "/R"])
+        .spawn()
+        .map_err(|e| format!("Failed to run installer: {}", e))?;
+
+    Ok(PendingUpdate {
+        version: staged.version.clone(),
+        target,
+        backup,
+        attempts: 0,
+    })
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn install(_staged: &StagedUpdate, _dir: &Path) -> Result<PendingUpdate, String> {
+    Err("Self-update is not supported on this platform".to_string())
+}
+
+/// Move `target` to `backup` and `new` into its place, undoing the first move if the second fails.
+#[cfg(any(target_os = "macos", test))]
+fn swap_in(new: &Path, target: &Path, backup: &Path) -> Result<(), String> {
+    if let Some(parent) = backup.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let _ = remove_path(backup);
+    fs::rename(target, backup).map_err(|e| format!("Failed to back up {:?}: {}", target, e))?;
+    if let Err(e) = fs::rename(new, target) {
+        let _ = fs::rename(backup, target);
+        return Err(format!("Failed to move update into {:?}: {}", target, e));
+    }
+    Ok(())
+}
+
+/// Put the backup back in place of a broken install. `target` is the executable inside the
+/// replaced bundle or install directory.
+fn roll_back(target: &Path, backup: &Path) -> Result<(), String> {
+    let installed = backup
+        .file_name()
+        .and_then(|name| target.ancestors().find(|p| p.file_name() == Some(name)))
+        .unwrap_or(target)
+        .to_path_buf();
+    if !backup.exists() {
+        return Err(format!("Backup {:?} is missing", backup));
+    }
+    restore(backup, &installed)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn restore(backup: &Path, installed: &Path) -> Result<(), String> {
+    let broken = installed.with_extension("broken");
+    let _ = remove_path(&broken);
+    if installed.exists() {
+        fs::rename(installed, &broken).map_err(|e| format!("Failed to move {:?}: {}", installed, e))?;
+    }
+    fs::rename(backup, installed).map_err(|e| format!("Failed to restore {:?}: {}", backup, e))?;
+    let _ = remove_path(&broken);
+    Ok(())
+}
+
+/// The install directory holds the running executable, so it can't be swapped out whole;
+/// restore it file by file instead.
+#[cfg(target_os = "windows")]
+fn restore(backup: &Path, installed: &Path) -> Result<(), String> {
+    mirror_dir(backup, installed).map_err(|e| format!("Failed to restore {:?}: {}", installed, e))?;
+    let _ = remove_path(backup);
+    Ok(())
+}
+
+/// Make `to` hold exactly the files of `from`. Files in use, like a running executable, are
+/// renamed out of the way since Windows won't delete them.
+#[cfg(any(target_os = "windows", test))]
+fn mirror_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(to)? {
+        let entry = entry?;
+        if !from.join(entry.file_name()).exists() {
+            set_aside(&entry.path())?;
+        }
+    }
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            if dest.is_file() {
+                set_aside(&dest)?;
+            }
+            mirror_dir(&entry.path(), &dest)?;
+        } else {
+            if dest.exists() {
+                set_aside(&dest)?;
+            }
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// Delete `path`, or if that fails move it to `<path>.broken`, left for the next mirror.
+#[cfg(any(target_os = "windows", test))]
+fn set_aside(path: &Path) -> std::io::Result<()> {
+    if remove_path(path).is_ok() {
+        return Ok(());
+    }
+    let mut broken = path.as_os_str().to_os_string();
+    broken.push(".broken");
+    let broken = PathBuf::from(broken);
+    let _ = remove_path(&broken);
+    fs::rename(path, broken)
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
     } else {
-        None
+        fs::remove_file(path)
+    }
+}
+
+/// Start `exe` and exit this process.
+fn relaunch(exe: &Path) {
+    match std::process::Command::new(exe).spawn() {
+        Ok(_) => std::process::exit(0),
+        Err(e) => eprintln!("[updater] Failed to relaunch {:?}: {}", exe, e),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    // Keypair and signature generated for these tests only
+    const TEST_PUBKEY: &str = "RWQBAgMEBQYHCCx19ESaySh77Ust1rJyHR98OWgSHvgd+gLQ4FD4rTF6";
+    const TEST_PAYLOAD: &[u8] = b"tokentorch update payload\n";
+    const TEST_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQBAgMEBQYHCPOk7AtGO4lL1SnGtUroUFvZFS9H4+DMThjzQLKQnsUVAx0r4rDGRWSj8S7Mm9xxHwc0AGdvkGi9i6AOxX24Zgo=
+trusted comment: version:99.0.0\tfile:TokenTorch.app.tar.gz
+K9I70Wj+t5bfdjX6XhbLpp6+H/lchxRuYmuZ9tpnP5xi0+n2vBy5VjkHpNblslsekCVC19DG22ONZhNC9gSFBw==
+";
+    const TEST_ASSET: &str = "TokenTorch.app.tar.gz";
+
+    fn v(s: &str) -> Version {
+        parse_version(s).unwrap()
+    }
+
+    #[test]
+    fn versions_follow_semver_precedence() {
+        assert!(v("0.4.0") > v("0.3.1"));
+        assert!(v("1.0.0") > v("0.9.9"));
+        assert!(v("v0.10.0") > v("0.9.0"));
+        assert!(v("0.7.0") > v("0.7.0-beta.1"));
+        assert!(v("0.7.0-beta.2") > v("0.7.0-beta.1"));
+        assert!(v("0.7.0-beta.1") > v("0.6.9"));
+        assert!(parse_version("nightly").is_none());
+    }
 
     #[test]
-    fn test_is_newer() {
-        assert!(is_newer("0.4.0", "0.3.1"));
-        assert!(is_newer("1.0.0", "0.9.9"));
-        assert!(is_newer("0.3.2", "0.3.1"));
-        assert!(!is_newer("0.3.1", "0.3.1"));
-        assert!(!is_newer("0.3.0", "0.3.1"));
-        assert!(!is_newer("0.2.9", "0.3.0"));
-        assert!(is_newer("0.10.0", "0.9.0"));
+    fn signature_must_match_payload_and_key() {
+        let verify = |data, key| verify_signature(data, TEST_SIGNATURE, key, "99.0.0", TEST_ASSET);
+        assert!(verify(TEST_PAYLOAD, TEST_PUBKEY).is_ok());
+        assert!(verify(b"tampered payload\n", TEST_PUBKEY).is_err());
+        let other_key = "RWQBAgMEBQYHCHm1Vi6P5lT5QHixEuipi6eQH4U65pW+1+DjkQutBJZk";
+        assert!(verify(TEST_PAYLOAD, other_key).is_err());
+    }
+
+    #[test]
+    fn signature_must_name_the_offered_version_and_file() {
+        assert!(verify_signature(TEST_PAYLOAD, TEST_SIGNATURE, TEST_PUBKEY, "v99.0.0", TEST_ASSET).is_ok());
+        // A valid signature replayed under a newer version, e.g. to push an old vulnerable build
+        assert!(verify_signature(TEST_PAYLOAD, TEST_SIGNATURE, TEST_PUBKEY, "100.0.0", TEST_ASSET).is_err());
+        assert!(verify_signature(TEST_PAYLOAD, TEST_SIGNATURE, TEST_PUBKEY, "99.0.0", "TokenTorch-setup.exe").is_err());
+    }
+
+    async fn mock_release_server(payload: &'static [u8]) -> MockServer {
+        let server = MockServer::start().await;
+        // GitHub sends the API `url` next to `html_url` and `browser_download_url`
+        let api = format!("{}/repos/{}/releases", server.uri(), GITHUB_REPO);
+        let asset = |id: u32, name: &str, link: &str| {
+            serde_json::json!({
+                "url": format!("{}/assets/{}", api, id),
+                "name": name,
+                "browser_download_url": format!("{}{}", server.uri(), link),
+            })
+        };
+        let releases = serde_json::json!([
+            { "url": format!("{}/4", api), "tag_name": "v100.0.0", "html_url": "", "draft": true },
+            { "url": format!("{}/3", api), "tag_name": "v99.1.0-beta.1", "html_url": "", "prerelease": true, "body": "Beta fixes" },
+            {
+                "url": format!("{}/2", api),
+                "tag_name": "v99.0.0",
+                "html_url": format!("{}/release", server.uri()),
+                "body": "## Stable\n\n- Faster polling",
+                "assets": [
+                    asset(1, "TokenTorch.app.tar.gz", "/dl/app"),
+                    asset(2, "TokenTorch.app.tar.gz.minisig", "/dl/app.minisig"),
+                    asset(3, "TokenTorch-setup.exe", "/dl/exe"),
+                ],
+            },
+            { "url": format!("{}/1", api), "tag_name": "v0.1.0", "html_url": "", "body": "Ancient history" },
+        ]);
+        Mock::given(method("GET"))
+            .and(path(format!("/repos/{}/releases", GITHUB_REPO)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(releases))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/dl/app"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(payload))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/dl/app.minisig"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(TEST_SIGNATURE))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn stages_verified_update_from_release_server() {
+        let server = mock_release_server(TEST_PAYLOAD).await;
+        let client = reqwest::Client::new();
+
+        let info = fetch_update(&client, &Feed::GitHub(&server.uri()), UpdateChannel::Stable, &[], Some(".app.tar.gz"))
+            .await
+            .unwrap();
+        assert_eq!(info.version, "99.0.0");
+        // The installer has no signature, so it never qualifies
+        assert!(fetch_update(&client, &Feed::GitHub(&server.uri()), UpdateChannel::Stable, &[], Some("-setup.exe"))
+            .await
+            .unwrap()
+            .asset
+            .is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let asset = info.asset.unwrap();
+        let staged = download_and_stage(&client, &info.version, &asset, TEST_PUBKEY, dir.path())
+            .await
+            .unwrap();
+        assert_eq!(fs::read(&staged.file).unwrap(), TEST_PAYLOAD);
+        assert_eq!(read_json::<StagedUpdate>(&dir.path().join("staged.json")), Some(staged));
+    }
+
+    #[tokio::test]
+    async fn channel_and_skip_pick_the_offered_release() {
+        let server = mock_release_server(TEST_PAYLOAD).await;
+        let client = reqwest::Client::new();
+        let check = |channel, skipped: Vec<Version>| {
+            let client = client.clone();
+            let uri = server.uri();
+            async move {
+                fetch_update(&client, &Feed::GitHub(&uri), channel, &skipped, None)
+                    .await
+                    .map(|info| info.version)
+            }
+        };
+
+        // Drafts are never offered; prereleases only on the beta channel
+        assert_eq!(check(UpdateChannel::Stable, vec![]).await.as_deref(), Some("99.0.0"));
+        assert_eq!(check(UpdateChannel::Beta, vec![]).await.as_deref(), Some("99.1.0-beta.1"));
+        assert_eq!(
+            check(UpdateChannel::Beta, vec![v("99.1.0-beta.1")]).await.as_deref(),
+            Some("99.0.0")
+        );
+        assert_eq!(check(UpdateChannel::Stable, vec![v("99.0.0")]).await, None);
+
+        // Notes cover every release between the running version and the offer
+        let info = fetch_update(&client, &Feed::GitHub(&server.uri()), UpdateChannel::Beta, &[], None)
+            .await
+            .unwrap();
+        let notes: Vec<&str> = info.notes.iter().map(|n| n.version.as_str()).collect();
+        assert_eq!(notes, ["99.1.0-beta.1", "99.0.0"]);
+    }
+
+    #[tokio::test]
+    async fn reads_static_manifest_with_relative_assets() {
+        let server = MockServer::start().await;
+        let manifest = serde_json::json!({
+            "releases": [
+                {
+                    "version": "99.0.0",
+                    "notes": "Internal build",
+                    "assets": [
+                        { "name": "TokenTorch.app.tar.gz", "url": "files/TokenTorch.app.tar.gz" },
+                        { "name": "TokenTorch.app.tar.gz.minisig", "url": "files/TokenTorch.app.tar.gz.minisig" },
+                    ],
+                },
+                { "version": "99.1.0-rc.1", "prerelease": true },
+            ],
+        });
+        Mock::given(method("GET"))
+            .and(path("/tokentorch/latest.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(manifest))
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/tokentorch/latest.json", server.uri());
+        let info = fetch_update(
+            &reqwest::Client::new(),
+            &Feed::Manifest(&url),
+            UpdateChannel::Stable,
+            &[],
+            Some(".app.tar.gz"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(info.version, "99.0.0");
+        assert_eq!(info.notes[0].markdown, "Internal build");
+        assert_eq!(
+            info.asset.unwrap().url,
+            format!("{}/tokentorch/files/TokenTorch.app.tar.gz", server.uri())
+        );
+    }
+
+    #[tokio::test]
+    async fn disabled_checks_make_no_requests() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "releases": [] })))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let settings = UpdateSettings {
+            enabled: false,
+            feed_url: Some(format!("{}/latest.json", server.uri())),
+            ..UpdateSettings::default()
+        };
+        assert!(check_for_update(&settings, &NetworkConfig::default()).await.is_none());
+        server.verify().await;
+        assert!(UpdateSettings {
+            feed_url: Some("ftp://intranet/latest.json".to_string()),
+            ..UpdateSettings::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_to_stage_tampered_download() {
+        let server = mock_release_server(b"tampered payload\n").await;
+        let client = reqwest::Client::new();
+        let info = fetch_update(&client, &Feed::GitHub(&server.uri()), UpdateChannel::Stable, &[], Some(".app.tar.gz"))
+            .await
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let result =
+            download_and_stage(&client, &info.version, &info.asset.unwrap(), TEST_PUBKEY, dir.path())
+                .await;
+        assert!(result.is_err());
+        assert!(!dir.path().join("staged.json").exists());
+    }
+
+    #[tokio::test]
+    async fn refuses_to_stage_release_relabeled_as_another_version() {
+        let server = mock_release_server(TEST_PAYLOAD).await;
+        let client = reqwest::Client::new();
+        let info = fetch_update(&client, &Feed::GitHub(&server.uri()), UpdateChannel::Stable, &[], Some(".app.tar.gz"))
+            .await
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let result =
+            download_and_stage(&client, "100.0.0", &info.asset.unwrap(), TEST_PUBKEY, dir.path()).await;
+        assert!(result.unwrap_err().contains("for version 99.0.0"));
+        assert!(!dir.path().join("staged.json").exists());
+    }
+
+    #[test]
+    fn unconfirmed_start_rolls_back_to_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("TokenTorch.app");
+        let new = dir.path().join("extract").join("TokenTorch.app");
+        let backup = dir.path().join("backup").join("TokenTorch.app");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("version"), "old").unwrap();
+        fs::create_dir_all(&new).unwrap();
+        fs::write(new.join("version"), "new").unwrap();
+
+        swap_in(&new, &target, &backup).unwrap();
+        assert_eq!(fs::read_to_string(target.join("version")).unwrap(), "new");
+
+        let mut pending = PendingUpdate {
+            version: "99.0.0".to_string(),
+            target: target.join("Contents/MacOS/tokentorch"),
+            backup: backup.clone(),
+            attempts: 0,
+        };
+        assert_eq!(startup_action(&mut pending, "99.0.0"), StartupAction::Continue);
+        assert_eq!(startup_action(&mut pending, "99.0.0"), StartupAction::RollBack);
+        assert_eq!(startup_action(&mut pending, "0.6.0"), StartupAction::Discard);
+
+        roll_back(&pending.target, &pending.backup).unwrap();
+        assert_eq!(fs::read_to_string(target.join("version")).unwrap(), "old");
+        assert!(!backup.exists());
+    }
+
+    #[test]
+    fn mirror_restores_backed_up_files_and_drops_new_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup = dir.path().join("backup");
+        let installed = dir.path().join("TokenTorch");
+        fs::create_dir_all(backup.join("resources")).unwrap();
+        fs::write(backup.join("tokentorch.exe"), "old").unwrap();
+        fs::write(backup.join("resources").join("icon.png"), "old").unwrap();
+        fs::create_dir_all(installed.join("resources")).unwrap();
+        fs::write(installed.join("tokentorch.exe"), "new").unwrap();
+        fs::write(installed.join("resources").join("added.png"), "new").unwrap();
+        fs::write(installed.join("uninstall.exe"), "new").unwrap();
+
+        mirror_dir(&backup, &installed).unwrap();
+        assert_eq!(fs::read_to_string(installed.join("tokentorch.exe")).unwrap(), "old");
+        assert_eq!(fs::read_to_string(installed.join("resources").join("icon.png")).unwrap(), "old");
+        assert!(!installed.join("resources").join("added.png").exists());
+        assert!(!installed.join("uninstall.exe").exists());
     }
 }
@@ -1,5 +1,9 @@
-use chrono::{DateTime, Local, LocalResult, NaiveDate, NaiveTime, TimeZone, Utc};
+use crate::plan::PlanAnalysis;
+use crate::profile::UsageProfile;
+use chrono::{Datelike, DateTime, Local, LocalResult, NaiveDate, NaiveTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UsageBucket {
@@ -7,16 +11,135 @@ pub struct UsageBucket {
     pub resets_at: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The usage endpoint's response, parsed field by field so schema changes degrade gently:
+/// any object with `utilization` / `resets_at` is a bucket, whatever it's called.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(from = "Map<String, Value>")]
 pub struct ApiUsageResponse {
-    pub five_hour: Option<UsageBucket>,
-    pub seven_day: Option<UsageBucket>,
-    pub seven_day_sonnet: Option<UsageBucket>,
-    pub seven_day_opus: Option<UsageBucket>,
-    pub seven_day_oauth_apps: Option<UsageBucket>,
-    pub seven_day_cowork: Option<UsageBucket>,
-    pub iguana_necktie: Option<UsageBucket>,
-    pub extra_usage: Option<serde_json::Value>,
+    /// Buckets by API name (`five_hour`, `seven_day`, `seven_day_opus`, ...).
+    pub buckets: BTreeMap<String, UsageBucket>,
+    pub extra_usage: Option<ExtraUsage>,
+    /// Buckets and `extra_usage` that failed to parse, with the reason.
+    pub bucket_errors: BTreeMap<String, String>,
+    /// Non-null fields that are neither buckets nor `extra_usage` (e.g. `plan`), kept for diagnostics.
+    pub other: BTreeMap<String, Value>,
+}
+
+impl From<Map<String, Value>> for ApiUsageResponse {
+    fn from(fields: Map<String, Value>) -> Self {
+        let mut response = ApiUsageResponse::default();
+        for (name, value) in fields {
+            match value {
+                Value::Null => {}
+                value if name == "extra_usage" => match serde_json::from_value(value) {
+                    Ok(extra) => response.extra_usage = Some(extra),
+                    Err(e) => {
+                        response.bucket_errors.insert(name, e.to_string());
+                    }
+                },
+                Value::Object(obj)
+                    if obj.contains_key("utilization") || obj.contains_key("resets_at") =>
+                {
+                    match serde_json::from_value::<UsageBucket>(Value::Object(obj)) {
+                        Ok(bucket) => {
+                            response.buckets.insert(name, bucket);
+                        }
+                        Err(e) => {
+                            response.bucket_errors.insert(name, e.to_string());
+                        }
+                    }
+                }
+                other => {
+                    response.other.insert(name, other);
+                }
+            }
+        }
+        response
+    }
+}
+
+impl ApiUsageResponse {
+    pub fn bucket(&self, kind: BucketKind) -> Option<&UsageBucket> {
+        self.buckets.get(kind.api_name())
+    }
+
+    /// Buckets other than session and weekly (e.g. `seven_day_opus`), by API name.
+    pub fn other_buckets(&self) -> impl Iterator<Item = (&String, &UsageBucket)> {
+        self.buckets.iter().filter(|(name, _)| {
+            ![BucketKind::Session, BucketKind::Weekly]
+                .iter()
+                .any(|kind| kind.api_name() == name.as_str())
+        })
+    }
+
+    /// Names of other buckets and unrecognized fields not yet in `reported`, which they're
+    /// added to.
+    pub fn newly_seen_fields(&self, reported: &mut BTreeSet<String>) -> Vec<String> {
+        self.other_buckets()
+            .map(|(name, _)| name)
+            .chain(self.other.keys())
+            .filter(|name| reported.insert(name.to_string()))
+            .cloned()
+            .collect()
+    }
+}
+
+/// A bucket the app has no policy for, shown with its utilization and reset only.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OtherBucket {
+    /// API name, e.g. `seven_day_opus`.
+    pub name: String,
+    /// E.g. "Seven day opus".
+    pub label: String,
+    pub utilization: f64,
+    pub resets_at: String,
+    pub reset_display: String,
+}
+
+impl OtherBucket {
+    fn new(name: &str, bucket: &UsageBucket, now: DateTime<Utc>) -> Self {
+        let mut label = name.replace('_', " ");
+        if let Some(first) = label.get(..1) {
+            label.replace_range(..1, &first.to_uppercase());
+        }
+        let reset_display = bucket
+            .resets_at
+            .parse::<DateTime<Utc>>()
+            .map(|at| format_reset_time((at - now).num_seconds() as f64, &at))
+            .unwrap_or_default();
+        Self {
+            name: name.to_string(),
+            label,
+            utilization: bucket.utilization,
+            resets_at: bucket.resets_at.clone(),
+            reset_display,
+        }
+    }
+}
+
+/// Pay-as-you-go spending beyond the plan. Amounts are in cents, as the API reports them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtraUsage {
+    pub is_enabled: bool,
+    pub monthly_limit: Option<f64>,
+    pub used_credits: Option<f64>,
+    pub currency: Option<String>,
+}
+
+/// Extra-usage spend for the popup, in currency units (dollars, not cents).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtraSpend {
+    pub spent: f64,
+    pub limit: Option<f64>,
+    pub remaining: Option<f64>,
+    /// Month-end spend at this month's rate so far, capped at the limit.
+    pub projected: f64,
+    pub budget: Option<f64>,
+    pub over_budget: bool,
+    pub color: UsageColor,
+    /// E.g. "$12.40 of $50.00 · $37.60 left · → $31.00 by month end".
+    pub display: String,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -28,16 +151,203 @@ pub enum UsageColor {
     Gray,
 }
 
+impl UsageColor {
+    /// Ordering for escalation checks: Gray (no data) < Green < Yellow < Red < RedBlink.
+    pub fn severity(self) -> u8 {
+        match self {
+            UsageColor::Gray => 0,
+            UsageColor::Green => 1,
+            UsageColor::Yellow => 2,
+            UsageColor::Red => 3,
+            UsageColor::RedBlink => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BucketKind {
+    Session,
+    Weekly,
+}
+
+impl BucketKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            BucketKind::Session => "Session",
+            BucketKind::Weekly => "Weekly",
+        }
+    }
+
+    /// Field name of this bucket in the usage response.
+    pub fn api_name(self) -> &'static str {
+        match self {
+            BucketKind::Session => "five_hour",
+            BucketKind::Weekly => "seven_day",
+        }
+    }
+
+    pub fn window_hours(self) -> f64 {
+        match self {
+            BucketKind::Session => SESSION_WINDOW_HOURS,
+            BucketKind::Weekly => WEEKLY_WINDOW_HOURS,
+        }
+    }
+}
+
+/// Percent cutoffs that map projected and current utilization to a color.
+/// A bar turns a color once its value is strictly above the cutoff.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorThresholds {
+    pub yellow_projected: f64,
+    pub red_projected: f64,
+    /// Blink when projected usage exceeds this, regardless of current utilization.
+    pub blink_projected: f64,
+    /// Also blink once current utilization exceeds this while the projection is red.
+    pub blink_utilization: Option<f64>,
+}
+
+impl ColorThresholds {
+    /// Session: short window, resets fast — only blink when actually limited or wildly over-projected
+    pub fn session() -> Self {
+        Self {
+            yellow_projected: 90.0,
+            red_projected: 100.0,
+            blink_projected: 200.0,
+            blink_utilization: Some(90.0),
+        }
+    }
+
+    /// Weekly: long window — tighter thresholds
+    pub fn weekly() -> Self {
+        Self {
+            yellow_projected: 90.0,
+            red_projected: 95.0,
+            blink_projected: 100.0,
+            blink_utilization: None,
+        }
+    }
+
+    pub fn color(&self, utilization: f64, projected: f64) -> UsageColor {
+        let limited = self
+            .blink_utilization
+            .is_some_and(|u| utilization > u && projected > self.red_projected);
+        if limited || projected > self.blink_projected {
+            UsageColor::RedBlink
+        } else if projected > self.red_projected {
+            UsageColor::Red
+        } else if projected > self.yellow_projected {
+            UsageColor::Yellow
+        } else {
+            UsageColor::Green
+        }
+    }
+
+    fn validate(&self, kind: BucketKind) -> Result<(), String> {
+        let ordered = self.yellow_projected <= self.red_projected
+            && self.red_projected <= self.blink_projected;
+        let finite = [self.yellow_projected, self.red_projected, self.blink_projected]
+            .iter()
+            .chain(self.blink_utilization.iter())
+            .all(|v| v.is_finite() && *v >= 0.0);
+        if !finite {
+            return Err(format!("{} thresholds must be non-negative numbers", kind.label()));
+        }
+        if !ordered {
+            return Err(format!(
+                "{} thresholds must satisfy yellow \u{2264} red \u{2264} blink",
+                kind.label()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Color thresholds for each bucket kind.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThresholdPolicy {
+    pub session: ColorThresholds,
+    pub weekly: ColorThresholds,
+    /// Colors use the projection's lower bound at this confidence. 0.5 colors by the point
+    /// estimate; higher values hold back red while a window has little data.
+    pub confidence: f64,
+}
+
+/// How the time left in a window is weighed when projecting usage.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ProjectionMode {
+    /// Usage spreads evenly over 8:00–22:00 local time.
+    #[default]
+    OnlineHours,
+    /// Weight the rest of the window by the hour-of-week profile learned from history,
+    /// falling back to online hours until there's enough of it.
+    Learned,
+}
+
+impl Default for ThresholdPolicy {
+    fn default() -> Self {
+        Self {
+            session: ColorThresholds::session(),
+            weekly: ColorThresholds::weekly(),
+            confidence: 0.5,
+        }
+    }
+}
+
+impl ThresholdPolicy {
+    pub fn for_kind(&self, kind: BucketKind) -> &ColorThresholds {
+        match kind {
+            BucketKind::Session => &self.session,
+            BucketKind::Weekly => &self.weekly,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.5..0.995).contains(&self.confidence) {
+            return Err("Confidence must be between 50% and 99%".to_string());
+        }
+        self.session.validate(BucketKind::Session)?;
+        self.weekly.validate(BucketKind::Weekly)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UsageBar {
+    pub kind: BucketKind,
     pub label: String,
     pub utilization: f64,
     pub resets_at: String,
     pub seconds_remaining: f64,
     pub projected: f64,
+    /// 80% band around `projected` (10th to 90th percentile).
+    #[serde(default)]
+    pub projected_low: f64,
+    #[serde(default)]
+    pub projected_high: f64,
     pub color: UsageColor,
     pub reset_display: String,
     pub gap_display: Option<String>,
+    /// When the bar reaches 100% at the current online burn rate, if before the reset (RFC 3339).
+    #[serde(default)]
+    pub limit_at: Option<String>,
+    /// `limit_at` for display, e.g. "hits 100% at 3:40 PM".
+    #[serde(default)]
+    pub limit_display: Option<String>,
+    /// Percent per online hour used so far in this window.
+    #[serde(default)]
+    pub current_rate: Option<f64>,
+    /// Percent per online hour that would land exactly on 100% at reset.
+    #[serde(default)]
+    pub safe_rate: Option<f64>,
+    /// E.g. "1.8x safe pace": current rate over safe rate.
+    #[serde(default)]
+    pub pace_display: Option<String>,
+    /// Set by the app from recorded events, e.g. "last window peaked at 87%".
+    #[serde(default)]
+    pub stats_display: Option<String>,
+    /// Set by the app when the user acknowledged this bar's current alert.
+    #[serde(default)]
+    pub acknowledged: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,6 +356,25 @@ pub struct UsageState {
     pub weekly: Option<UsageBar>,
     pub last_updated: String,
     pub error: Option<String>,
+    /// Set when pay-as-you-go extra usage is enabled on the account.
+    #[serde(default)]
+    pub extra: Option<ExtraSpend>,
+    /// Set by the app from history once there's enough of it.
+    #[serde(default)]
+    pub plan: Option<PlanAnalysis>,
+    /// Buckets beyond session and weekly that the API started reporting.
+    #[serde(default)]
+    pub other_buckets: Vec<OtherBucket>,
+}
+
+impl UsageState {
+    pub fn bars(&self) -> impl Iterator<Item = &UsageBar> {
+        self.session.iter().chain(self.weekly.iter())
+    }
+
+    pub fn bars_mut(&mut self) -> impl Iterator<Item = &mut UsageBar> {
+        self.session.iter_mut().chain(self.weekly.iter_mut())
+    }
 }
 
 const SESSION_WINDOW_HOURS: f64 = 5.0;
@@ -54,15 +383,87 @@ const ONLINE_START_HOUR: u32 = 8;
 const ONLINE_END_HOUR: u32 = 22;
 const SECONDS_PER_HOUR: f64 = 3600.0;
 const MIN_PROJECTION_ELAPSED_SECONDS: f64 = 10.0 * 60.0;
+/// Windows are at most a week; don't search for a limit time further out than this.
+const MAX_LIMIT_LOOKAHEAD_DAYS: u32 = 14;
+/// Relative spread of the burn rate (std dev / mean) assumed until history says otherwise.
+pub const DEFAULT_RATE_SPREAD: f64 = 0.5;
+/// z-score of the displayed band's edges (10th / 90th percentile).
+const BAND_Z: f64 = 1.2816;
+
+/// Measures how much usage time lies between two instants, in seconds of an average hour.
+#[derive(Debug, Clone, Copy)]
+pub enum Clock<'a> {
+    OnlineHours,
+    Profile(&'a UsageProfile),
+}
+
+impl Clock<'_> {
+    pub fn seconds_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> f64 {
+        match self {
+            Clock::OnlineHours => online_seconds_between(start, end),
+            Clock::Profile(profile) => profile.weighted_seconds_between(start, end),
+        }
+    }
+
+    /// The moment `seconds` of usage time have passed after `start`.
+    pub fn advance(&self, start: DateTime<Utc>, seconds: f64) -> Option<DateTime<Utc>> {
+        match self {
+            Clock::OnlineHours => advance_online_seconds(start, seconds),
+            Clock::Profile(profile) => {
+                profile.advance_weighted(start, seconds, MAX_LIMIT_LOOKAHEAD_DAYS as usize * 24)
+            }
+        }
+    }
+}
+
+/// How uncertain a projection is and how sure it must be to color a bar.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Uncertainty {
+    /// Std dev / mean of the burn rate, from usage history.
+    pub rate_spread: f64,
+    /// See `ThresholdPolicy::confidence`.
+    pub confidence: f64,
+}
+
+impl Default for Uncertainty {
+    fn default() -> Self {
+        Self {
+            rate_spread: DEFAULT_RATE_SPREAD,
+            confidence: 0.5,
+        }
+    }
+}
 
-pub fn compute_usage_bar(label: &str, bucket: &UsageBucket, window_hours: f64) -> UsageBar {
-    compute_usage_bar_at(label, bucket, window_hours, Utc::now())
+pub fn compute_usage_bar(
+    kind: BucketKind,
+    bucket: &UsageBucket,
+    thresholds: &ColorThresholds,
+    uncertainty: &Uncertainty,
+    clock: Clock,
+) -> UsageBar {
+    let window_hours = kind.window_hours();
+    project_usage_bar(kind, bucket, window_hours, thresholds, uncertainty, clock, Utc::now())
 }
 
+#[cfg(test)]
 fn compute_usage_bar_at(
-    label: &str,
+    kind: BucketKind,
+    bucket: &UsageBucket,
+    window_hours: f64,
+    thresholds: &ColorThresholds,
+    now: DateTime<Utc>,
+) -> UsageBar {
+    let uncertainty = Uncertainty::default();
+    project_usage_bar(kind, bucket, window_hours, thresholds, &uncertainty, Clock::OnlineHours, now)
+}
+
+fn project_usage_bar(
+    kind: BucketKind,
     bucket: &UsageBucket,
     window_hours: f64,
+    thresholds: &ColorThresholds,
+    uncertainty: &Uncertainty,
+    clock: Clock,
     now: DateTime<Utc>,
 ) -> UsageBar {
     let resets_at = bucket
@@ -74,8 +475,8 @@ fn compute_usage_bar_at(
     let seconds_remaining = remaining.num_seconds().max(0) as f64;
 
     let window_start = resets_at - hours_to_duration(window_hours);
-    let elapsed_online_seconds = online_seconds_between(window_start, now);
-    let remaining_online_seconds = online_seconds_between(now, resets_at);
+    let elapsed_online_seconds = clock.seconds_between(window_start, now);
+    let remaining_online_seconds = clock.seconds_between(now, resets_at);
     let total_online_window_seconds = elapsed_online_seconds + remaining_online_seconds;
 
     let projected = if elapsed_online_seconds < MIN_PROJECTION_ELAPSED_SECONDS
@@ -88,24 +489,67 @@ fn compute_usage_bar_at(
         burn_rate * (total_online_window_seconds / SECONDS_PER_HOUR)
     };
 
-    let is_session = label == "Session";
-    let color = if is_session {
-        compute_session_color(bucket.utilization, projected)
+    // The still-to-come part of the projection is uncertain: more so when the rate has been
+    // erratic, less so as the window runs out
+    let elapsed_fraction = if total_online_window_seconds > 0.0 {
+        elapsed_online_seconds / total_online_window_seconds
     } else {
-        compute_weekly_color(projected)
+        1.0
     };
+    let sigma = (projected - bucket.utilization).max(0.0)
+        * uncertainty.rate_spread
+        * (1.0 - elapsed_fraction).max(0.0).sqrt();
+    let bound = |z: f64| (projected + z * sigma).max(bucket.utilization);
+    let projected_low = bound(-BAND_Z);
+    let projected_high = bound(BAND_Z);
+
+    let color = thresholds.color(bucket.utilization, bound(-z_score(uncertainty.confidence)));
     let reset_display = format_reset_time(seconds_remaining, &resets_at);
     let gap_display = compute_gap_display(bucket.utilization, projected, remaining_online_seconds);
 
+    let current_rate = (elapsed_online_seconds >= MIN_PROJECTION_ELAPSED_SECONDS)
+        .then(|| bucket.utilization / (elapsed_online_seconds / SECONDS_PER_HOUR));
+    let safe_rate = (remaining_online_seconds > 0.0 && bucket.utilization < 100.0).then(|| {
+        (100.0 - bucket.utilization) / (remaining_online_seconds / SECONDS_PER_HOUR)
+    });
+    let pace_display = match (current_rate, safe_rate) {
+        (Some(current), Some(safe)) if safe > 0.0 => {
+            Some(format!("{:.1}x safe pace", current / safe))
+        }
+        _ => None,
+    };
+
+    let limit_at = if projected > 100.0
+        && bucket.utilization < 100.0
+        && elapsed_online_seconds >= MIN_PROJECTION_ELAPSED_SECONDS
+    {
+        let burn_per_online_second = bucket.utilization / elapsed_online_seconds;
+        clock
+            .advance(now, (100.0 - bucket.utilization) / burn_per_online_second)
+            .filter(|at| *at < resets_at)
+    } else {
+        None
+    };
+
     UsageBar {
-        label: label.to_string(),
+        kind,
+        label: kind.label().to_string(),
         utilization: bucket.utilization,
         resets_at: bucket.resets_at.clone(),
         seconds_remaining,
         projected,
+        projected_low,
+        projected_high,
         color,
         reset_display,
         gap_display,
+        limit_at: limit_at.map(|at| at.to_rfc3339()),
+        limit_display: limit_at.map(|at| format_limit_time(&at, &now)),
+        current_rate,
+        safe_rate,
+        pace_display,
+        stats_display: None,
+        acknowledged: false,
     }
 }
 
@@ -123,7 +567,61 @@ fn resolve_local_datetime(date: NaiveDate, time: NaiveTime) -> Option<DateTime<L
     }
 }
 
-fn online_seconds_between(start: DateTime<Utc>, end: DateTime<Utc>) -> f64 {
+/// The moment `online_seconds` of online time (8:00–22:00 local) have passed after `start`.
+pub(crate) fn advance_online_seconds(start: DateTime<Utc>, online_seconds: f64) -> Option<DateTime<Utc>> {
+    let start_local = start.with_timezone(&Local);
+    let start_day = start_local.date_naive();
+    let online_start = NaiveTime::from_hms_opt(ONLINE_START_HOUR, 0, 0)
+        .expect("online start hour constant must be valid");
+    let online_end = NaiveTime::from_hms_opt(ONLINE_END_HOUR, 0, 0)
+        .expect("online end hour constant must be valid");
+
+    let mut day = start_day;
+    let mut remaining = online_seconds.max(0.0);
+    for _ in 0..MAX_LIMIT_LOOKAHEAD_DAYS {
+        if let (Some(day_online_start), Some(day_online_end)) = (
+            resolve_local_datetime(day, online_start),
+            resolve_local_datetime(day, online_end),
+        ) {
+            let segment_start = if day == start_day {
+                start_local.max(day_online_start)
+            } else {
+                day_online_start
+            };
+            if day_online_end > segment_start {
+                let segment_seconds = (day_online_end - segment_start).num_seconds() as f64;
+                if remaining <= segment_seconds {
+                    let at = segment_start + chrono::Duration::seconds(remaining.ceil() as i64);
+                    return Some(at.with_timezone(&Utc));
+                }
+                remaining -= segment_seconds;
+            }
+        }
+        day = day.succ_opt()?;
+    }
+    None
+}
+
+/// One-sided z-score for confidence `p` (0.5 <= p < 1), Abramowitz & Stegun 26.2.23.
+fn z_score(p: f64) -> f64 {
+    if p <= 0.5 {
+        return 0.0;
+    }
+    let t = (-2.0 * (1.0 - p).ln()).sqrt();
+    t - (2.515517 + 0.802853 * t + 0.010328 * t * t)
+        / (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t)
+}
+
+fn format_limit_time(at: &DateTime<Utc>, now: &DateTime<Utc>) -> String {
+    let local = at.with_timezone(&Local);
+    if local.date_naive() == now.with_timezone(&Local).date_naive() {
+        format!("hits 100% at {}", local.format("%l:%M %p").to_string().trim())
+    } else {
+        format!("hits 100% {}", local.format("%a %l:%M %p").to_string().replace("  ", " "))
+    }
+}
+
+pub(crate) fn online_seconds_between(start: DateTime<Utc>, end: DateTime<Utc>) -> f64 {
     if end <= start {
         return 0.0;
     }
@@ -173,32 +671,6 @@ fn online_seconds_between(start: DateTime<Utc>, end: DateTime<Utc>) -> f64 {
     total_seconds.max(0.0)
 }
 
-/// Session: short window, resets fast — only blink when actually limited or wildly over-projected
-fn compute_session_color(utilization: f64, projected: f64) -> UsageColor {
-    if (utilization > 90.0 && projected > 100.0) || projected > 200.0 {
-        UsageColor::RedBlink
-    } else if projected > 100.0 {
-        UsageColor::Red
-    } else if projected > 90.0 {
-        UsageColor::Yellow
-    } else {
-        UsageColor::Green
-    }
-}
-
-/// Weekly: long window — tighter thresholds
-fn compute_weekly_color(projected: f64) -> UsageColor {
-    if projected > 100.0 {
-        UsageColor::RedBlink
-    } else if projected > 95.0 {
-        UsageColor::Red
-    } else if projected > 90.0 {
-        UsageColor::Yellow
-    } else {
-        UsageColor::Green
-    }
-}
-
 fn format_reset_time(seconds_remaining: f64, resets_at: &DateTime<Utc>) -> String {
     if seconds_remaining <= 0.0 {
         return "resetting...".to_string();
@@ -247,22 +719,114 @@ fn compute_gap_display(
     Some(format!("{} gap", time))
 }
 
-pub fn compute_state(response: &ApiUsageResponse) -> UsageState {
-    let session = response
-        .five_hour
-        .as_ref()
-        .map(|b| compute_usage_bar("Session", b, SESSION_WINDOW_HOURS));
-
-    let weekly = response
-        .seven_day
-        .as_ref()
-        .map(|b| compute_usage_bar("Weekly", b, WEEKLY_WINDOW_HOURS));
+/// `rate_spread` gives each bucket's burn-rate spread from history, if known; `profile` is
+/// the learned usage profile, if projections should follow it.
+pub fn compute_state(
+    response: &ApiUsageResponse,
+    policy: &ThresholdPolicy,
+    rate_spread: impl Fn(BucketKind) -> Option<f64>,
+    profile: Option<&UsageProfile>,
+) -> UsageState {
+    let clock = profile.map_or(Clock::OnlineHours, Clock::Profile);
+    let bar = |kind: BucketKind| {
+        let uncertainty = Uncertainty {
+            rate_spread: rate_spread(kind).unwrap_or(DEFAULT_RATE_SPREAD),
+            confidence: policy.confidence,
+        };
+        response
+            .bucket(kind)
+            .map(|b| compute_usage_bar(kind, b, policy.for_kind(kind), &uncertainty, clock))
+    };
+    let session = bar(BucketKind::Session);
+    let weekly = bar(BucketKind::Weekly);
+    let now = Utc::now();
 
     UsageState {
         session,
         weekly,
-        last_updated: Utc::now().to_rfc3339(),
+        last_updated: now.to_rfc3339(),
         error: None,
+        extra: None,
+        plan: None,
+        other_buckets: response
+            .other_buckets()
+            .map(|(name, bucket)| OtherBucket::new(name, bucket, now))
+            .collect(),
+    }
+}
+
+/// Spend so far this (calendar, UTC) month and where it's heading. `budget` is the user's
+/// own ceiling in currency units; projecting past it turns the row red and alerts.
+pub fn compute_extra_spend(
+    extra: &ExtraUsage,
+    budget: Option<f64>,
+    now: DateTime<Utc>,
+) -> Option<ExtraSpend> {
+    if !extra.is_enabled {
+        return None;
+    }
+    let spent = extra.used_credits.unwrap_or(0.0) / 100.0;
+    let limit = extra.monthly_limit.map(|cents| cents / 100.0);
+
+    let month_start = Utc
+        .with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .unwrap_or(now);
+    let days_in_month = days_in_month(now.year(), now.month());
+    // At least a day elapsed, so a charge on the 1st doesn't project to 30x
+    let elapsed_days = ((now - month_start).num_seconds() as f64 / 86_400.0).max(1.0);
+    let mut projected = spent / elapsed_days * days_in_month;
+    if let Some(limit) = limit {
+        projected = projected.min(limit);
+    }
+
+    let over_budget = budget.is_some_and(|b| projected > b);
+    let color = if over_budget || limit.is_some_and(|l| spent >= l) {
+        UsageColor::Red
+    } else if limit.is_some_and(|l| projected >= l) {
+        UsageColor::Yellow
+    } else {
+        UsageColor::Green
+    };
+
+    let money = |amount: f64| format_money(amount, extra.currency.as_deref());
+    let mut display = money(spent);
+    if let Some(limit) = limit {
+        display.push_str(&format!(
+            " of {} \u{00B7} {} left",
+            money(limit),
+            money((limit - spent).max(0.0))
+        ));
+    }
+    display.push_str(&format!(" \u{00B7} \u{2192} {} by month end", money(projected)));
+
+    Some(ExtraSpend {
+        spent,
+        limit,
+        remaining: limit.map(|l| (l - spent).max(0.0)),
+        projected,
+        budget,
+        over_budget,
+        color,
+        display,
+    })
+}
+
+fn days_in_month(year: i32, month: u32) -> f64 {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    match (
+        NaiveDate::from_ymd_opt(year, month, 1),
+        NaiveDate::from_ymd_opt(next_year, next_month, 1),
+    ) {
+        (Some(start), Some(end)) => (end - start).num_days() as f64,
+        _ => 30.0,
+    }
+}
+
+fn format_money(amount: f64, currency: Option<&str>) -> String {
+    match currency {
+        None | Some("USD") => format!("${:.2}", amount),
+        Some(code) => format!("{:.2} {}", amount, code),
     }
 }
 
@@ -299,6 +863,46 @@ pub fn tray_title(state: &UsageState) -> String {
     format!("{} {}", s, w)
 }
 
+/// Short one-line summary of a bar, used for the tray menu info rows.
+pub fn bar_summary(bar: &UsageBar) -> String {
+    format!("{}: {:.0}% \u{00B7} {}", bar.label, bar.utilization, bar.reset_display)
+}
+
+/// Plain-text status report, suitable for pasting into a chat.
+pub fn status_text(state: &UsageState) -> String {
+    let mut lines: Vec<String> = [&state.session, &state.weekly]
+        .iter()
+        .filter_map(|b| b.as_ref())
+        .map(|bar| {
+            let mut line = format!(
+                "{}: {:.0}% (projected {:.0}%), {}",
+                bar.label, bar.utilization, bar.projected, bar.reset_display
+            );
+            if let Some(gap) = &bar.gap_display {
+                line.push_str(&format!(", {}", gap));
+            }
+            if let Some(limit) = &bar.limit_display {
+                line.push_str(&format!(", {}", limit));
+            }
+            line
+        })
+        .collect();
+
+    for other in &state.other_buckets {
+        lines.push(format!("{}: {:.0}%, {}", other.label, other.utilization, other.reset_display));
+    }
+    if let Some(extra) = &state.extra {
+        lines.push(format!("Extra usage: {}", extra.display));
+    }
+    if let Some(err) = &state.error {
+        lines.push(format!("Error: {}", err));
+    }
+    if lines.is_empty() {
+        lines.push("No usage data".to_string());
+    }
+    lines.join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -341,7 +945,7 @@ mod tests {
         let reset = local_to_utc(2026, 1, 16, 9, 0);
         let usage = bucket(60.0, reset);
 
-        let bar = compute_usage_bar_at("Weekly", &usage, 24.0, now);
+        let bar = compute_usage_bar_at(BucketKind::Weekly, &usage, 24.0, &ColorThresholds::weekly(), now);
 
         assert_approx(bar.projected, 70.0);
         assert!(bar.projected < 100.0);
@@ -353,18 +957,87 @@ mod tests {
         let reset = local_to_utc(2026, 1, 16, 9, 0);
         let usage = bucket(96.0, reset);
 
-        let bar = compute_usage_bar_at("Weekly", &usage, 24.0, now);
+        let bar = compute_usage_bar_at(BucketKind::Weekly, &usage, 24.0, &ColorThresholds::weekly(), now);
 
         assert_eq!(bar.gap_display.as_deref(), Some("1h 30m gap"));
     }
 
+    #[test]
+    fn limit_time_skips_offline_hours() {
+        let now = local_to_utc(2026, 1, 15, 21, 45);
+        let reset = local_to_utc(2026, 1, 16, 13, 0);
+        let usage = bucket(90.0, reset);
+
+        let bar = compute_usage_bar_at(BucketKind::Weekly, &usage, 24.0, &ColorThresholds::weekly(), now);
+
+        // 10% left at 90% per 8.75 online hours: 15 min tonight, the rest from 8:00 tomorrow
+        let expected = local_to_utc(2026, 1, 16, 8, 43) + chrono::Duration::seconds(20);
+        assert_eq!(bar.limit_at.as_deref(), Some(expected.to_rfc3339().as_str()));
+        assert_eq!(bar.limit_display.as_deref(), Some("hits 100% Fri 8:43 AM"));
+
+        // 90% in 8.75 online hours vs. 10% left over 5.25 online hours before the reset
+        assert_approx(bar.current_rate.unwrap(), 10.3);
+        assert_approx(bar.safe_rate.unwrap(), 1.9);
+        assert_eq!(bar.pace_display.as_deref(), Some("5.4x safe pace"));
+
+        let calm = compute_usage_bar_at(BucketKind::Weekly, &bucket(20.0, reset), 24.0, &ColorThresholds::weekly(), now);
+        assert_eq!(calm.limit_at, None);
+    }
+
+    #[test]
+    fn confidence_holds_back_red_early_in_a_window() {
+        let now = local_to_utc(2026, 1, 15, 10, 0);
+        let usage = bucket(45.0, local_to_utc(2026, 1, 15, 13, 0));
+        let bar_at = |rate_spread: f64, confidence: f64| {
+            let uncertainty = Uncertainty { rate_spread, confidence };
+            let thresholds = ColorThresholds::session();
+            project_usage_bar(BucketKind::Session, &usage, 5.0, &thresholds, &uncertainty, Clock::OnlineHours, now)
+        };
+
+        // 2 of 5 online hours in: 45% now, 112.5% projected
+        let point = bar_at(DEFAULT_RATE_SPREAD, 0.5);
+        assert_approx(point.projected, 112.5);
+        assert_approx(point.projected_low, 79.0);
+        assert_approx(point.projected_high, 146.0);
+        assert_eq!(point.color, UsageColor::Red);
+
+        assert_eq!(bar_at(DEFAULT_RATE_SPREAD, 0.8).color, UsageColor::Yellow);
+        assert_eq!(bar_at(DEFAULT_RATE_SPREAD, 0.9).color, UsageColor::Green);
+        // A steady burn rate in history keeps the band narrow, so red stays red
+        assert_eq!(bar_at(0.1, 0.9).color, UsageColor::Red);
+    }
+
+    #[test]
+    fn learned_profile_weights_the_time_left() {
+        let now = local_to_utc(2026, 1, 15, 12, 0);
+        let usage = bucket(20.0, local_to_utc(2026, 1, 15, 22, 0));
+        // Ten times busier from 18:00 to 22:00 than the rest of the day
+        let hourly: Vec<f64> = (0..7 * 24)
+            .map(|bin| if (18..22).contains(&(bin % 24)) { 10.0 } else { 1.0 })
+            .collect();
+        let total: f64 = hourly.iter().sum();
+        let profile = UsageProfile {
+            weights: hourly.iter().map(|w| w / total).collect(),
+            observed_hours: 100.0,
+        };
+        let bar_with = |clock: Clock| {
+            let (thresholds, uncertainty) = (ColorThresholds::weekly(), Uncertainty::default());
+            project_usage_bar(BucketKind::Weekly, &usage, 24.0, &thresholds, &uncertainty, clock, now)
+        };
+
+        // 4 of 14 online hours in
+        assert_approx(bar_with(Clock::OnlineHours).projected, 70.0);
+        // 14 quiet hours in (5.6 weighted), 6 quiet and 4 busy to go (18.4 weighted)
+        assert_approx(bar_with(Clock::Profile(&profile)).projected, 85.7);
+    }
+
     #[test]
     fn reset_display_stays_wall_clock_time() {
         let now = local_to_utc(2026, 1, 15, 21, 0);
         let reset = local_to_utc(2026, 1, 16, 9, 0);
         let usage = bucket(96.0, reset);
 
-        let bar = compute_usage_bar_at("Weekly", &usage, 24.0, now);
+        let bar = compute_usage_bar_at(BucketKind::Weekly, &usage, 24.0, &ColorThresholds::weekly(), now);
 
         assert_eq!(bar.reset_display, "resets in 12h 0m");
     }
@@ -375,8 +1048,145 @@ mod tests {
         let reset = local_to_utc(2026, 1, 15, 13, 0);
         let usage = bucket(12.0, reset);
 
-        let bar = compute_usage_bar_at("Session", &usage, 5.0, now);
+        let bar = compute_usage_bar_at(BucketKind::Session, &usage, 5.0, &ColorThresholds::session(), now);
 
         assert_eq!(bar.projected, 12.0);
     }
+
+    #[test]
+    fn default_thresholds_match_bucket_behavior() {
+        let session = ColorThresholds::session();
+        assert_eq!(session.color(50.0, 150.0), UsageColor::Red);
+        assert_eq!(session.color(95.0, 105.0), UsageColor::RedBlink);
+        assert_eq!(session.color(50.0, 250.0), UsageColor::RedBlink);
+
+        let weekly = ColorThresholds::weekly();
+        assert_eq!(weekly.color(50.0, 92.0), UsageColor::Yellow);
+        assert_eq!(weekly.color(50.0, 97.0), UsageColor::Red);
+        assert_eq!(weekly.color(50.0, 101.0), UsageColor::RedBlink);
+    }
+
+    #[test]
+    fn custom_thresholds_change_color() {
+        let now = local_to_utc(2026, 1, 15, 21, 0);
+        let reset = local_to_utc(2026, 1, 16, 9, 0);
+        let usage = bucket(60.0, reset);
+        let relaxed = ColorThresholds {
+            yellow_projected: 60.0,
+            red_projected: 80.0,
+            blink_projected: 120.0,
+            blink_utilization: None,
+        };
+
+        let bar = compute_usage_bar_at(BucketKind::Weekly, &usage, 24.0, &relaxed, now);
+
+        assert_eq!(bar.color, UsageColor::Yellow);
+    }
+
+    #[test]
+    fn threshold_policy_rejects_unordered_cutoffs() {
+        let mut policy = ThresholdPolicy::default();
+        assert!(policy.validate().is_ok());
+
+        policy.weekly.red_projected = 120.0;
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn status_text_lists_bars_and_gap() {
+        let now = local_to_utc(2026, 1, 15, 21, 0);
+        let reset = local_to_utc(2026, 1, 16, 9, 0);
+        let weekly = compute_usage_bar_at(
+            BucketKind::Weekly,
+            &bucket(96.0, reset),
+            24.0,
+            &ColorThresholds::weekly(),
+            now,
+        );
+        let state = UsageState {
+            session: None,
+            weekly: Some(weekly),
+            last_updated: now.to_rfc3339(),
+            error: None,
+            extra: None,
+            plan: None,
+            other_buckets: vec![OtherBucket::new(
+                "seven_day_opus",
+                &bucket(12.0, reset),
+                now,
+            )],
+        };
+
+        assert_eq!(
+            status_text(&state),
+            "Weekly: 96% (projected 112%), resets in 12h 0m, 1h 30m gap, hits 100% at 9:30 PM\n\
+             Seven day opus: 12%, resets in 12h 0m"
+        );
+    }
+
+    #[test]
+    fn response_keeps_good_buckets_when_others_break() {
+        let response: ApiUsageResponse = serde_json::from_value(serde_json::json!({
+            "five_hour": { "utilization": 40.0, "resets_at": "2026-01-16T09:00:00Z" },
+            "seven_day": { "utilization": "n/a", "resets_at": "2026-01-20T09:00:00Z" },
+            "seven_day_research": { "utilization": 3.0, "resets_at": "2026-01-20T09:00:00Z", "tier": 2 },
+            "seven_day_opus": null,
+            "extra_usage": { "is_enabled": false },
+            "plan": "max"
+        }))
+        .unwrap();
+
+        assert_eq!(response.bucket(BucketKind::Session).unwrap().utilization, 40.0);
+        assert!(response.bucket(BucketKind::Weekly).is_none());
+        assert!(response.bucket_errors.contains_key("seven_day"));
+        assert_eq!(response.buckets["seven_day_research"].utilization, 3.0);
+        assert_eq!(response.other.keys().collect::<Vec<_>>(), ["plan"]);
+        assert_eq!(response.extra_usage, Some(ExtraUsage::default()));
+
+        let state = compute_state(&response, &ThresholdPolicy::default(), |_| None, None);
+        assert!(state.session.is_some());
+        assert!(state.weekly.is_none());
+        assert_eq!(state.other_buckets.len(), 1);
+        assert_eq!(state.other_buckets[0].label, "Seven day research");
+
+        // New names are reported once
+        let mut reported = BTreeSet::new();
+        assert_eq!(response.newly_seen_fields(&mut reported), ["seven_day_research", "plan"]);
+        assert!(response.newly_seen_fields(&mut reported).is_empty());
+    }
+
+    #[test]
+    fn extra_spend_projects_to_month_end() {
+        let extra = ExtraUsage {
+            is_enabled: true,
+            monthly_limit: Some(5000.0),
+            used_credits: Some(1000.0),
+            currency: None,
+        };
+        // 10 of 30 days into the month
+        let now = Utc.with_ymd_and_hms(2026, 11, 11, 0, 0, 0).unwrap();
+
+        let spend = compute_extra_spend(&extra, None, now).unwrap();
+        assert_approx(spend.spent, 10.0);
+        assert_approx(spend.projected, 30.0);
+        assert_eq!(spend.remaining, Some(40.0));
+        assert_eq!(spend.color, UsageColor::Green);
+        assert_eq!(
+            spend.display,
+            "$10.00 of $50.00 \u{00B7} $40.00 left \u{00B7} \u{2192} $30.00 by month end"
+        );
+
+        let budgeted = compute_extra_spend(&extra, Some(25.0), now).unwrap();
+        assert!(budgeted.over_budget);
+        assert_eq!(budgeted.color, UsageColor::Red);
+
+        // Capped at the account limit, which it will reach
+        let heavy = ExtraUsage { used_credits: Some(4000.0), ..extra.clone() };
+        let heavy = compute_extra_spend(&heavy, None, now).unwrap();
+        assert_approx(heavy.projected, 50.0);
+        assert_eq!(heavy.color, UsageColor::Yellow);
+
+        let disabled = ExtraUsage { is_enabled: false, ..extra };
+        assert!(compute_extra_spend(&disabled, None, now).is_none());
+    }
 }
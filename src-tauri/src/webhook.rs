@@ -0,0 +1,518 @@
+//! Outbound webhooks for team chat: color changes and window resets, retried with backoff.
+
+use crate::api;
+use crate::events::{Event, EventKind};
+use crate::net::NetworkConfig;
+use crate::usage::{BucketKind, UsageBar, UsageColor, UsageState};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const USER_AGENT: &str = "tokentorch";
+/// Deliveries are given up after this many failed attempts.
+const MAX_ATTEMPTS: u32 = 5;
+/// Wait before the first retry; doubles with every further attempt.
+const RETRY_BASE: Duration = Duration::from_secs(30);
+
+pub const DEFAULT_TEMPLATE: &str =
+    "{profile}: {label} {event} — {utilization}% now, {projected}% projected, {reset}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum WebhookFormat {
+    /// Every field of the event as JSON, plus the rendered `text`.
+    #[default]
+    Json,
+    Slack,
+    Discord,
+    /// `url` is a room's `/send/m.room.message` endpoint; needs an access token.
+    Matrix,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Webhook {
+    pub enabled: bool,
+    pub url: String,
+    pub format: WebhookFormat,
+    /// Sent as a Bearer token (Matrix access token, or for a generic endpoint). Saved in the
+    /// OS keychain, never in the settings store.
+    pub token: Option<String>,
+    /// Keychain entry holding `token`, which is all the settings store keeps of it.
+    pub token_ref: Option<String>,
+    /// Color changes fire when a bar gets worse and reaches this color.
+    pub min_color: UsageColor,
+    pub on_reset: bool,
+    /// Message text. Placeholders: {profile}, {label}, {event}, {color}, {utilization},
+    /// {projected}, {reset}.
+    pub template: String,
+}
+
+impl Default for Webhook {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            url: String::new(),
+            format: WebhookFormat::default(),
+            token: None,
+            token_ref: None,
+            min_color: UsageColor::Red,
+            on_reset: false,
+            template: DEFAULT_TEMPLATE.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Trigger {
+    ColorChange { from: UsageColor, to: UsageColor },
+    Reset { peak: f64 },
+    /// From the settings "Test" button.
+    Test,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WebhookEvent {
+    pub trigger: Trigger,
+    /// Active profile name (or org ID), so a shared channel knows whose usage it is.
+    pub profile: String,
+    pub kind: BucketKind,
+    pub label: String,
+    pub color: UsageColor,
+    pub utilization: f64,
+    pub projected: f64,
+    pub resets_at: String,
+    pub reset_display: String,
+}
+
+impl WebhookEvent {
+    pub fn new(trigger: Trigger, bar: &UsageBar, profile: &str) -> Self {
+        Self {
+            trigger,
+            profile: profile.to_string(),
+            kind: bar.kind,
+            label: bar.label.clone(),
+            color: bar.color,
+            utilization: bar.utilization,
+            projected: bar.projected,
+            resets_at: bar.resets_at.clone(),
+            reset_display: bar.reset_display.clone(),
+        }
+    }
+
+    fn description(&self) -> String {
+        match self.trigger {
+            Trigger::ColorChange { to, .. } => format!("turned {}", color_name(to)),
+            Trigger::Reset { peak } => format!("window reset after peaking at {:.0}%", peak),
+            Trigger::Test => "test message".to_string(),
+        }
+    }
+}
+
+/// Color changes between `previous` and `current`, plus the resets among `events`.
+pub fn transitions(
+    previous: Option<&UsageState>,
+    current: &UsageState,
+    events: &[Event],
+    profile: &str,
+) -> Vec<WebhookEvent> {
+    let mut out = Vec::new();
+    for bar in current.bars() {
+        let before = previous.and_then(|p| p.bars().find(|b| b.kind == bar.kind));
+        if let Some(before) = before.filter(|b| b.color != bar.color) {
+            if before.color != UsageColor::Gray && bar.color != UsageColor::Gray {
+                let trigger = Trigger::ColorChange {
+                    from: before.color,
+                    to: bar.color,
+                };
+                out.push(WebhookEvent::new(trigger, bar, profile));
+            }
+        }
+    }
+    for event in events {
+        if let EventKind::Reset { peak } = event.event {
+            if let Some(bar) = current.bars().find(|b| b.kind == event.kind) {
+                out.push(WebhookEvent::new(Trigger::Reset { peak }, bar, profile));
+            }
+        }
+    }
+    out
+}
+
+impl Webhook {
+    pub fn validate(&self) -> Result<(), String> {
+        let url = reqwest::Url::parse(self.url.trim()).map_err(|e| format!("Invalid webhook URL: {}", e))?;
+        let has_token = self.token.as_deref().is_some_and(|t| !t.trim().is_empty()) || self.token_ref.is_some();
+        match url.scheme() {
+            "https" => {}
+            // The token is sent as a Bearer header and must not cross the network in cleartext
+            "http" if !has_token || api::is_loopback(&url) => {}
+            "http" => return Err("Webhooks with a token must use https:// unless they're on this machine".to_string()),
+            _ => return Err("Webhook URL must start with http:// or https://".to_string()),
+        }
+        if self.template.trim().is_empty() {
+            return Err("Webhook message template can't be empty".to_string());
+        }
+        if self.format == WebhookFormat::Matrix && !has_token {
+            return Err("Matrix webhooks need an access token".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn wants(&self, event: &WebhookEvent) -> bool {
+        self.enabled
+            && match event.trigger {
+                Trigger::ColorChange { from, to } => {
+                    to.severity() > from.severity() && to.severity() >= self.min_color.severity()
+                }
+                Trigger::Reset { .. } => self.on_reset,
+                Trigger::Test => true,
+            }
+    }
+
+    pub fn message(&self, event: &WebhookEvent) -> String {
+        [
+            ("{profile}", event.profile.clone()),
+            ("{label}", event.label.clone()),
+            ("{event}", event.description()),
+            ("{color}", color_name(event.color).to_string()),
+            ("{utilization}", format!("{:.0}", event.utilization)),
+            ("{projected}", format!("{:.0}", event.projected)),
+            ("{reset}", event.reset_display.clone()),
+        ]
+        .iter()
+        .fold(self.template.clone(), |text, (key, value)| text.replace(key, value))
+    }
+
+    /// The settings store's copy: the token is left out, its keychain reference kept.
+    pub fn without_token(&self) -> Self {
+        Self {
+            token: None,
+            ..self.clone()
+        }
+    }
+
+    /// Post `event` once.
+    pub async fn send(&self, client: &reqwest::Client, event: &WebhookEvent) -> Result<(), String> {
+        self.post(client, event, &transaction_id()).await
+    }
+
+    /// Post `event`; Matrix rooms get it under `transaction_id`, so a retry that reaches the
+    /// server twice shows up once.
+    async fn post(&self, client: &reqwest::Client, event: &WebhookEvent, transaction_id: &str) -> Result<(), String> {
+        let text = self.message(event);
+        let url = self.url.trim();
+        let request = match self.format {
+            WebhookFormat::Json => {
+                let mut body = serde_json::to_value(event).map_err(|e| e.to_string())?;
+                body["text"] = json!(text);
+                client.post(url).json(&body)
+            }
+            WebhookFormat::Slack => client.post(url).json(&json!({ "text": text })),
+            WebhookFormat::Discord => client.post(url).json(&json!({ "content": text })),
+            WebhookFormat::Matrix => client
+                .put(format!("{}/{}", url.trim_end_matches('/'), transaction_id))
+                .json(&json!({ "msgtype": "m.text", "body": text })),
+        };
+        let request = match self.token.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Network error: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Webhook error: HTTP {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+pub fn client(network: &NetworkConfig) -> reqwest::Client {
+    network.build_client(reqwest::Client::builder(), USER_AGENT)
+}
+
+#[derive(Debug)]
+struct Delivery {
+    hook: Webhook,
+    event: WebhookEvent,
+    /// Kept across retries.
+    transaction_id: String,
+    attempts: u32,
+    due: Instant,
+}
+
+/// Deliveries waiting for their first attempt or a retry. In memory only: a missed alert
+/// is stale by the next start anyway.
+#[derive(Debug, Default)]
+pub struct WebhookQueue {
+    pending: VecDeque<Delivery>,
+}
+
+impl WebhookQueue {
+    /// Queue each event for every hook that wants it.
+    pub fn enqueue(&mut self, hooks: &[Webhook], events: &[WebhookEvent], now: Instant) {
+        for event in events {
+            for hook in hooks.iter().filter(|h| h.wants(event)) {
+                self.pending.push_back(Delivery {
+                    hook: hook.clone(),
+                    event: event.clone(),
+                    transaction_id: transaction_id(),
+                    attempts: 0,
+                    due: now,
+                });
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    fn take_due(&mut self, now: Instant) -> Vec<Delivery> {
+        let (due, waiting) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|d| d.due <= now);
+        self.pending = waiting;
+        due.into()
+    }
+
+    fn retry(&mut self, mut delivery: Delivery, now: Instant, err: &str) {
+        delivery.attempts += 1;
+        if delivery.attempts >= MAX_ATTEMPTS {
+            eprintln!("[webhook] giving up on {} after {} attempts: {}", delivery.hook.url, delivery.attempts, err);
+            return;
+        }
+        eprintln!("[webhook] {} failed (attempt {}): {}", delivery.hook.url, delivery.attempts, err);
+        delivery.due = now + RETRY_BASE * 2u32.pow(delivery.attempts - 1);
+        self.pending.push_back(delivery);
+    }
+}
+
+/// Send every delivery that's due, putting failures back for a later retry.
+pub async fn flush(queue: &Mutex<WebhookQueue>, client: &reqwest::Client, now: Instant) {
+    let due = queue.lock().unwrap().take_due(now);
+    for delivery in due {
+        let sent = delivery
+            .hook
+            .post(client, &delivery.event, &delivery.transaction_id)
+            .await;
+        if let Err(e) = sent {
+            queue.lock().unwrap().retry(delivery, now, &e);
+        }
+    }
+}
+
+fn color_name(color: UsageColor) -> &'static str {
+    match color {
+        UsageColor::Green => "green",
+        UsageColor::Yellow => "yellow",
+        UsageColor::Red => "red",
+        UsageColor::RedBlink => "at the limit",
+        UsageColor::Gray => "unknown",
+    }
+}
+
+/// Matrix needs a fresh transaction ID per message, or it drops the repeat.
+fn transaction_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    format!(
+        "tokentorch-{}-{}",
+        chrono::Utc::now().timestamp_millis(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::ProxyMode;
+    use crate::test_support::event;
+    use wiremock::matchers::{body_json, header, method, path, path_regex};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn test_client() -> reqwest::Client {
+        client(&NetworkConfig {
+            proxy_mode: ProxyMode::Direct,
+            request_timeout_secs: 1,
+            ..NetworkConfig::default()
+        })
+    }
+
+    const TURNED_RED: Trigger = Trigger::ColorChange {
+        from: UsageColor::Yellow,
+        to: UsageColor::Red,
+    };
+
+    #[test]
+    fn fires_on_escalation_to_the_chosen_color() {
+        let hook = Webhook::default();
+        assert!(hook.wants(&event(TURNED_RED)));
+        assert!(!hook.wants(&event(Trigger::ColorChange {
+            from: UsageColor::Green,
+            to: UsageColor::Yellow
+        })));
+        assert!(!hook.wants(&event(Trigger::ColorChange {
+            from: UsageColor::Red,
+            to: UsageColor::Yellow
+        })));
+        assert!(!hook.wants(&event(Trigger::Reset { peak: 90.0 })));
+        assert_eq!(
+            hook.message(&event(TURNED_RED)),
+            "Alice: Weekly turned red — 64% now, 131% projected, resets Fri 10:00 AM"
+        );
+
+        let bad = Webhook {
+            format: WebhookFormat::Matrix,
+            url: "https://matrix.example/_matrix/client/v3/rooms/!r/send/m.room.message".to_string(),
+            ..Webhook::default()
+        };
+        assert_eq!(bad.validate().unwrap_err(), "Matrix webhooks need an access token");
+        // Loaded from the store, the token is in the keychain
+        let stored = Webhook {
+            token_ref: Some("webhook_token_0".to_string()),
+            ..bad
+        };
+        assert!(stored.validate().is_ok());
+
+        // Tokens only go over plain http to this machine
+        let plain = |url: &str| Webhook {
+            url: url.to_string(),
+            ..stored.clone()
+        };
+        assert!(plain("http://matrix.example/_matrix/client/v3/rooms/!r/send/m.room.message")
+            .validate()
+            .is_err());
+        assert!(plain("http://127.0.0.1:8008/_matrix/client/v3/rooms/!r/send/m.room.message")
+            .validate()
+            .is_ok());
+        let tokenless = Webhook {
+            url: "http://chat.intranet.example/hook".to_string(),
+            ..Webhook::default()
+        };
+        assert!(tokenless.validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn formats_payloads_for_each_service() {
+        let server = MockServer::start().await;
+        let text = "Alice: Weekly test message — 64% now, 131% projected, resets Fri 10:00 AM";
+        Mock::given(method("POST"))
+            .and(path("/slack"))
+            .and(body_json(json!({ "text": text })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/discord"))
+            .and(body_json(json!({ "content": text })))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path_regex("^/rooms/r/send/m.room.message/tokentorch-"))
+            .and(header("authorization", "Bearer syt-token"))
+            .and(body_json(json!({ "msgtype": "m.text", "body": text })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = test_client();
+        let send = |format: WebhookFormat, path: &str, token: Option<&str>| {
+            let hook = Webhook {
+                url: format!("{}{}", server.uri(), path),
+                format,
+                token: token.map(str::to_string),
+                ..Webhook::default()
+            };
+            let client = client.clone();
+            async move { hook.send(&client, &event(Trigger::Test)).await }
+        };
+        send(WebhookFormat::Slack, "/slack", None).await.unwrap();
+        send(WebhookFormat::Discord, "/discord", None).await.unwrap();
+        send(WebhookFormat::Matrix, "/rooms/r/send/m.room.message", Some("syt-token"))
+            .await
+            .unwrap();
+        // Unmatched: the stand-in answers 404
+        let err = send(WebhookFormat::Json, "/generic", None).await.unwrap_err();
+        assert_eq!(err, "Webhook error: HTTP 404 Not Found");
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_are_retried_with_backoff() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let hook = Webhook {
+            url: server.uri(),
+            ..Webhook::default()
+        };
+        let queue = Mutex::new(WebhookQueue::default());
+        let client = test_client();
+        let start = Instant::now();
+        queue.lock().unwrap().enqueue(&[hook], &[event(TURNED_RED)], start);
+
+        flush(&queue, &client, start).await;
+        assert!(!queue.lock().unwrap().is_empty());
+        // Not due yet
+        flush(&queue, &client, start + Duration::from_secs(5)).await;
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+
+        flush(&queue, &client, start + RETRY_BASE).await;
+        assert!(queue.lock().unwrap().is_empty());
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+        let body: serde_json::Value = requests[1].body_json().unwrap();
+        assert_eq!(body["trigger"], json!({ "type": "ColorChange", "from": "Yellow", "to": "Red" }));
+        assert_eq!(body["profile"], "Alice");
+    }
+
+    #[tokio::test]
+    async fn matrix_retries_reuse_the_transaction_id() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(502))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let hook = Webhook {
+            url: format!("{}/rooms/r/send/m.room.message", server.uri()),
+            format: WebhookFormat::Matrix,
+            token: Some("syt-token".to_string()),
+            ..Webhook::default()
+        };
+        let queue = Mutex::new(WebhookQueue::default());
+        let client = test_client();
+        let start = Instant::now();
+        queue.lock().unwrap().enqueue(&[hook], &[event(TURNED_RED)], start);
+        flush(&queue, &client, start).await;
+        flush(&queue, &client, start + RETRY_BASE).await;
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].url.path(), requests[1].url.path());
+    }
+}
@@ -11,20 +11,47 @@ function colorClass(color) {
   }
 }
 
+function isAlert(color) {
+  return color === 'Red' || color === 'RedBlink';
+}
+
+function themeName(theme) {
+  switch (theme) {
+    case 'ColorblindSafe': return 'colorblind-safe';
+    case 'HighContrast': return 'high-contrast';
+    case 'Monochrome': return 'monochrome';
+    default: return 'default';
+  }
+}
+
+function applyTheme(theme) {
+  document.documentElement.dataset.theme = themeName(theme);
+}
+
+function applyBlinkSettings(settings) {
+  document.documentElement.dataset.blink = settings && settings.mode === 'Off' ? 'off' : 'on';
+}
+
 function updateBar(prefix, bar) {
+  const label = document.querySelector(`#${prefix}-row .label`);
   const fill = document.getElementById(`${prefix}-fill`);
   const pct = document.getElementById(`${prefix}-pct`);
   const reset = document.getElementById(`${prefix}-reset`);
   const proj = document.getElementById(`${prefix}-projected`);
   const gap = document.getElementById(`${prefix}-gap`);
+  const ack = document.getElementById(`${prefix}-ack`);
 
   if (!bar) {
     fill.style.width = '0%';
     fill.className = 'bar-fill gray';
     pct.textContent = '--%';
     reset.textContent = 'no data';
+    reset.title = '';
     proj.textContent = '';
+    proj.title = '';
     gap.textContent = '';
+    ack.hidden = true;
+    label.title = '';
     return;
   }
 
@@ -32,9 +59,20 @@ function updateBar(prefix, bar) {
   fill.style.width = `${utilization}%`;
   fill.className = `bar-fill ${colorClass(bar.color)}`;
   pct.textContent = `${Math.round(bar.utilization)}%`;
-  reset.textContent = bar.reset_display;
+  reset.textContent = [bar.reset_display, bar.pace_display].filter(Boolean).join(' · ');
+  reset.title = bar.safe_rate != null && bar.current_rate != null
+    ? `Using ${bar.current_rate.toFixed(1)}%/h; ${bar.safe_rate.toFixed(1)}%/h per online hour lasts until reset`
+    : '';
   proj.textContent = `→ ${Math.round(bar.projected)}%`;
-  gap.textContent = bar.gap_display || '';
+  proj.title = bar.projected_high > bar.projected_low
+    ? `Likely between ${Math.round(bar.projected_low)}% and ${Math.round(bar.projected_high)}% at reset`
+    : '';
+  gap.textContent = [bar.gap_display, bar.limit_display].filter(Boolean).join(' · ');
+  label.title = bar.stats_display || '';
+  ack.hidden = !isAlert(bar.color) || bar.acknowledged;
+  ack.onclick = () => {
+    invoke('acknowledge_alert', { kind: bar.kind }).catch(() => {});
+  };
 
   // Projected marker
   const container = fill.parentElement;
@@ -52,14 +90,40 @@ function updateBar(prefix, bar) {
   }
 }
 
+function money(amount) {
+  return `$${amount.toFixed(2)}`;
+}
+
+function updateExtra(extra) {
+  const row = document.getElementById('extra-row');
+  row.hidden = !extra;
+  if (!extra) return;
+
+  const fill = document.getElementById('extra-fill');
+  const share = extra.limit ? Math.min(extra.spent / extra.limit * 100, 100) : 0;
+  fill.style.width = `${share}%`;
+  fill.className = `bar-fill ${colorClass(extra.color)}`;
+  document.getElementById('extra-spent').textContent = money(extra.spent);
+  document.getElementById('extra-detail').textContent = extra.display;
+  document.getElementById('extra-budget').textContent =
+    extra.over_budget ? `over ${money(extra.budget)} budget` : '';
+}
+
 function updateUI(state) {
   if (!state) return;
 
   updateBar('session', state.session);
   updateBar('weekly', state.weekly);
+  updateExtra(state.extra);
+
+  const others = document.getElementById('other-buckets');
+  const buckets = state.other_buckets || [];
+  others.textContent = buckets.map((b) => `${b.label} ${Math.round(b.utilization)}%`).join(' · ');
+  others.title = buckets.map((b) => `${b.label}: ${b.reset_display}`).join('\n');
 
   const errorMsg = document.getElementById('error-msg');
   errorMsg.textContent = state.error || '';
+  document.getElementById('plan-msg').textContent = state.plan ? state.plan.display : '';
 }
 
 async function loadData() {
@@ -90,6 +154,18 @@ async function init() {
   await listen('usage-updated', (event) => {
     updateUI(event.payload);
   });
+  await listen('theme-changed', (event) => {
+    applyTheme(event.payload);
+  });
+  await listen('blink-settings-changed', (event) => {
+    applyBlinkSettings(event.payload);
+  });
+
+  try {
+    const config = await invoke('get_config');
+    applyTheme(config.theme);
+    applyBlinkSettings(config.blink);
+  } catch (_) {}
 
   // Load current data with retries
   const loaded = await loadData();